@@ -0,0 +1,631 @@
+use crate::preemptive::kept_hypotheses;
+use crate::sample::{random_index, SampleBuffer, Select};
+use crate::search::inliers;
+use crate::termination::{achieved_confidence, sprt_required_iterations, BestHypothesis};
+use crate::{
+    Consensus, ConsensusReport, CountScorer, Estimator, MaxIterations, Model, ReportingConsensus,
+    Sampler, Scorer, Sprt, Termination, TerminationReason, Uniform, Verification,
+};
+use alloc::vec::Vec;
+use rand_core::RngCore;
+
+/// ARRSAC from the paper "A Comparative Analysis of RANSAC Techniques Leading to Adaptive Real-Time Random
+/// Sample Consensus" by Raguram, Frahm, and Pollefeys.
+///
+/// ARRSAC combines the bounded run time of [`PreemptiveRansac`](crate::PreemptiveRansac) with the adaptivity
+/// of RANSAC. Hypotheses are generated and verified with the [`Sprt`] on the first block of data. Whenever a new
+/// best hypothesis is found, the number of hypotheses to generate is adapted to its inlier ratio and additional
+/// hypotheses are generated from samples of its inliers. The surviving hypotheses are then evaluated
+/// breadth-first on the remaining blocks of data, discarding the worst half after every block. An additional
+/// [`Termination`] criterion, such as a [`Cancellation`](crate::Cancellation), can stop the generation sooner.
+///
+/// The hypothesis with the most inliers wins. The hypotheses can instead be scored by any [`Scorer`] whose score is
+/// a sum over the datapoints, such as the [`MsacScorer`](crate::MsacScorer), while the SPRT keeps using the inlier
+/// threshold. Since the hypotheses are evaluated on the data in order, the data must be shuffled.
+#[derive(Clone, Debug)]
+pub struct Arrsac<R, S = Uniform, C = CountScorer, T = MaxIterations> {
+    threshold: f64,
+    scorer: C,
+    max_candidate_hypotheses: usize,
+    block_size: usize,
+    confidence: f64,
+    inner_hypotheses: usize,
+    initial_epsilon: f64,
+    initial_delta: f64,
+    termination: T,
+    rng: R,
+    sampler: S,
+    inliers: Vec<usize>,
+    rejected_samples: usize,
+}
+
+impl<R> Arrsac<R>
+where
+    R: RngCore,
+{
+    /// Creates a new `Arrsac` which considers a datapoint an inlier when its residual is below `threshold`.
+    ///
+    /// `rng` is used to draw the samples. By default, at most `200` hypotheses are generated on the first
+    /// block of `100` datapoints, and fewer once they are drawn with a confidence of `0.99`.
+    pub fn new(threshold: f64, rng: R) -> Self {
+        Self {
+            threshold,
+            scorer: CountScorer::new(threshold),
+            max_candidate_hypotheses: 200,
+            block_size: 100,
+            confidence: 0.99,
+            inner_hypotheses: 10,
+            initial_epsilon: 0.1,
+            initial_delta: 0.01,
+            termination: MaxIterations::new(usize::MAX),
+            rng,
+            sampler: Uniform,
+            inliers: Vec::new(),
+            rejected_samples: 0,
+        }
+    }
+}
+
+impl<R, S, T> Arrsac<R, S, CountScorer, T>
+where
+    R: RngCore,
+{
+    /// The residual below which a datapoint is considered an inlier.
+    ///
+    /// Default: specified in [`Arrsac::new`]
+    pub fn threshold(self, threshold: f64) -> Self {
+        Self {
+            threshold,
+            scorer: CountScorer::new(threshold),
+            ..self
+        }
+    }
+}
+
+impl<R, S, C, T> Arrsac<R, S, C, T>
+where
+    R: RngCore,
+{
+    /// The maximum number of hypotheses to generate, including the ones rejected by the SPRT.
+    ///
+    /// Default: `200`
+    pub fn max_candidate_hypotheses(self, max_candidate_hypotheses: usize) -> Self {
+        Self {
+            max_candidate_hypotheses,
+            ..self
+        }
+    }
+
+    /// The number of datapoints evaluated between halving the hypotheses. Hypotheses are generated and
+    /// verified on the first block.
+    ///
+    /// Default: `100`
+    pub fn block_size(self, block_size: usize) -> Self {
+        Self { block_size, ..self }
+    }
+
+    /// The probability of having drawn at least one all-inlier sample at which hypothesis generation stops.
+    ///
+    /// Default: `0.99`
+    pub fn confidence(self, confidence: f64) -> Self {
+        Self { confidence, ..self }
+    }
+
+    /// The number of hypotheses generated from samples of the inliers of every new best hypothesis.
+    ///
+    /// Default: `10`
+    pub fn inner_hypotheses(self, inner_hypotheses: usize) -> Self {
+        Self {
+            inner_hypotheses,
+            ..self
+        }
+    }
+
+    /// The initial estimate of the inlier ratio used by the SPRT. See [`Sprt::initial_epsilon`].
+    ///
+    /// Default: `0.1`
+    pub fn initial_epsilon(self, initial_epsilon: f64) -> Self {
+        Self {
+            initial_epsilon,
+            ..self
+        }
+    }
+
+    /// The initial estimate of the probability that a datapoint is consistent with a bad model used by
+    /// the SPRT. See [`Sprt::initial_delta`].
+    ///
+    /// Default: `0.01`
+    pub fn initial_delta(self, initial_delta: f64) -> Self {
+        Self {
+            initial_delta,
+            ..self
+        }
+    }
+
+    /// The [`Sampler`] which draws the minimal samples of the initial hypotheses. The samples drawn from the
+    /// inliers of a new best hypothesis are always drawn uniformly.
+    ///
+    /// Default: [`Uniform`]
+    pub fn sampler<X>(self, sampler: X) -> Arrsac<R, X, C, T> {
+        Arrsac {
+            threshold: self.threshold,
+            scorer: self.scorer,
+            max_candidate_hypotheses: self.max_candidate_hypotheses,
+            block_size: self.block_size,
+            confidence: self.confidence,
+            inner_hypotheses: self.inner_hypotheses,
+            initial_epsilon: self.initial_epsilon,
+            initial_delta: self.initial_delta,
+            termination: self.termination,
+            rng: self.rng,
+            sampler,
+            inliers: self.inliers,
+            rejected_samples: self.rejected_samples,
+        }
+    }
+
+    /// An additional [`Termination`] criterion which can stop generating hypotheses before the confidence or
+    /// the maximum number of hypotheses is reached. The hypotheses generated so far are still evaluated, unless the
+    /// criterion interrupts the search, such as a [`Cancellation`](crate::Cancellation), in which case the best
+    /// hypothesis on the blocks evaluated so far wins.
+    ///
+    /// Default: no additional criterion
+    pub fn termination<X>(self, termination: X) -> Arrsac<R, S, C, X> {
+        Arrsac {
+            threshold: self.threshold,
+            scorer: self.scorer,
+            max_candidate_hypotheses: self.max_candidate_hypotheses,
+            block_size: self.block_size,
+            confidence: self.confidence,
+            inner_hypotheses: self.inner_hypotheses,
+            initial_epsilon: self.initial_epsilon,
+            initial_delta: self.initial_delta,
+            termination,
+            rng: self.rng,
+            sampler: self.sampler,
+            inliers: self.inliers,
+            rejected_samples: self.rejected_samples,
+        }
+    }
+
+    /// The [`Scorer`] which scores the hypotheses and decides which datapoints are inliers, while the SPRT keeps
+    /// using the inlier threshold. The hypotheses are compared by the sum of the scores of the datapoints they were
+    /// evaluated on, so the score of the data must be the sum of the scores of the datapoints.
+    ///
+    /// Default: [`CountScorer`] with the threshold specified in [`Arrsac::new`]
+    pub fn scorer<D>(self, scorer: D) -> Arrsac<R, S, D, T> {
+        Arrsac {
+            threshold: self.threshold,
+            scorer,
+            max_candidate_hypotheses: self.max_candidate_hypotheses,
+            block_size: self.block_size,
+            confidence: self.confidence,
+            inner_hypotheses: self.inner_hypotheses,
+            initial_epsilon: self.initial_epsilon,
+            initial_delta: self.initial_delta,
+            termination: self.termination,
+            rng: self.rng,
+            sampler: self.sampler,
+            inliers: self.inliers,
+            rejected_samples: self.rejected_samples,
+        }
+    }
+
+    /// The number of minimal samples rejected by [`Estimator::is_sample_valid`] during the last search.
+    pub fn rejected_samples(&self) -> usize {
+        self.rejected_samples
+    }
+
+    /// Generates hypotheses and verifies them on the first block of data.
+    ///
+    /// Returns the surviving hypotheses along with their score on the first block, and records
+    /// the statistics of the generation in `report`.
+    fn initial_hypotheses<E, Data, I>(
+        &mut self,
+        estimator: &E,
+        data: I,
+        len: usize,
+        report: &mut ConsensusReport<E::Model>,
+    ) -> Vec<(E::Model, f64)>
+    where
+        E: Estimator<Data>,
+        I: Iterator<Item = Data> + Clone,
+        S: Sampler,
+        C: Scorer,
+        T: Termination,
+    {
+        let m = E::MIN_SAMPLES;
+        let block_len = self.block_size.clamp(1, len);
+        let block = data.clone().take(block_len);
+        let mut sprt = Sprt::new(self.threshold)
+            .initial_epsilon(self.initial_epsilon)
+            .initial_delta(self.initial_delta);
+        let mut hypotheses: Vec<(E::Model, f64)> = Vec::new();
+        let mut best_inliers = 0;
+        let mut target = self.max_candidate_hypotheses;
+        let mut generated = 0;
+        self.sampler.reset();
+        self.termination.reset();
+        let mut stopped = false;
+        let mut indices = SampleBuffer::new(m);
+        while generated < target {
+            if self.termination.should_stop(generated) {
+                stopped = true;
+                break;
+            }
+            generated += 1;
+            if !self.sampler.sample(&mut self.rng, len, &mut indices) {
+                continue;
+            }
+            let sample = Select::new(data.clone(), &indices);
+            if !estimator.is_sample_valid(sample.clone()) {
+                self.rejected_samples += 1;
+                continue;
+            }
+            for model in estimator.estimate(sample.clone()) {
+                report.hypotheses += 1;
+                if !estimator.is_model_valid(&model, sample.clone()) {
+                    report.rejected_hypotheses += 1;
+                    continue;
+                }
+                let Verification::Accepted {
+                    inliers: block_inliers,
+                } = sprt.verify(&model, block.clone())
+                else {
+                    report.rejected_hypotheses += 1;
+                    continue;
+                };
+                if block_inliers > best_inliers {
+                    best_inliers = block_inliers;
+                    let inlier_ratio = block_inliers as f64 / block_len as f64;
+                    sprt.update_epsilon(inlier_ratio);
+                    self.termination.update(&BestHypothesis {
+                        score: block_inliers as f64,
+                        inliers: block_inliers,
+                        len: block_len,
+                        sample_size: m,
+                        decision_threshold: Some(sprt.decision_threshold()),
+                    });
+                    target = sprt_required_iterations(
+                        self.confidence,
+                        inlier_ratio,
+                        m,
+                        sprt.decision_threshold(),
+                        self.max_candidate_hypotheses,
+                    );
+                    generated += self.generate_from_inliers(
+                        estimator,
+                        &model,
+                        data.clone(),
+                        block.clone(),
+                        &mut sprt,
+                        &mut hypotheses,
+                        report,
+                    );
+                }
+                let score = self
+                    .scorer
+                    .score(block.clone().map(|data| model.residual(&data)));
+                hypotheses.push((model, score.value));
+            }
+        }
+        report.iterations = generated;
+        report.rejected_samples = self.rejected_samples;
+        report.termination = if stopped {
+            self.termination.reason()
+        } else if target < self.max_candidate_hypotheses {
+            TerminationReason::Converged
+        } else {
+            TerminationReason::MaxIterations
+        };
+        hypotheses
+    }
+
+    /// Generates hypotheses from samples of the inliers of `model` in the first block of data and adds the
+    /// ones that pass verification to `hypotheses`, recording the statistics in `report`.
+    ///
+    /// Returns the number of samples drawn.
+    #[allow(clippy::too_many_arguments)]
+    fn generate_from_inliers<E, Data, I, B>(
+        &mut self,
+        estimator: &E,
+        model: &E::Model,
+        data: I,
+        block: B,
+        sprt: &mut Sprt,
+        hypotheses: &mut Vec<(E::Model, f64)>,
+        report: &mut ConsensusReport<E::Model>,
+    ) -> usize
+    where
+        E: Estimator<Data>,
+        I: Iterator<Item = Data> + Clone,
+        B: Iterator<Item = Data> + Clone,
+        C: Scorer,
+    {
+        let m = E::MIN_SAMPLES;
+        self.inliers.clear();
+        self.inliers
+            .extend(inliers(model, block.clone(), self.threshold));
+        if self.inliers.len() <= m {
+            return 0;
+        }
+        let mut indices = SampleBuffer::new(m);
+        for _ in 0..self.inner_hypotheses {
+            let mut drawn = 0;
+            while drawn < m {
+                let ix = self.inliers[random_index(&mut self.rng, self.inliers.len())];
+                if !indices[..drawn].contains(&ix) {
+                    indices[drawn] = ix;
+                    drawn += 1;
+                }
+            }
+            indices.sort_unstable();
+            let sample = Select::new(data.clone(), &indices);
+            if !estimator.is_sample_valid(sample.clone()) {
+                self.rejected_samples += 1;
+                continue;
+            }
+            for model in estimator.estimate(sample.clone()) {
+                report.hypotheses += 1;
+                if !estimator.is_model_valid(&model, sample.clone()) {
+                    report.rejected_hypotheses += 1;
+                    continue;
+                }
+                match sprt.verify(&model, block.clone()) {
+                    Verification::Accepted { .. } => {
+                        let score = self
+                            .scorer
+                            .score(block.clone().map(|data| model.residual(&data)));
+                        hypotheses.push((model, score.value));
+                    }
+                    Verification::Rejected { .. } => report.rejected_hypotheses += 1,
+                }
+            }
+        }
+        self.inner_hypotheses
+    }
+}
+
+impl<R, S, C, T> Arrsac<R, S, C, T>
+where
+    R: RngCore,
+    S: Sampler,
+    C: Scorer,
+    T: Termination,
+{
+    /// Runs the search and reports the best model, without its inliers, score, or confidence.
+    fn search<E, Data, I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        E: Estimator<Data>,
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.rejected_samples = 0;
+        let len = data.clone().count();
+        if len < E::MIN_SAMPLES {
+            return ConsensusReport::too_few_data();
+        }
+        let mut report = ConsensusReport {
+            model: None,
+            inliers: Vec::new(),
+            score: None,
+            iterations: 0,
+            hypotheses: 0,
+            rejected_samples: 0,
+            rejected_hypotheses: 0,
+            termination: TerminationReason::MaxIterations,
+            confidence: 0.0,
+        };
+        let mut hypotheses = self.initial_hypotheses(estimator, data.clone(), len, &mut report);
+        let candidates = hypotheses.len();
+
+        // Evaluate the hypotheses breadth-first on the remaining blocks, halving them after every block.
+        let block_size = self.block_size.max(1);
+        for (ix, data) in data.enumerate().skip(block_size) {
+            if hypotheses.len() <= 1 {
+                break;
+            }
+            if ix % block_size == 0 && self.termination.is_interrupted() {
+                report.termination = self.termination.reason();
+                break;
+            }
+            for (model, score) in &mut hypotheses {
+                *score += self
+                    .scorer
+                    .score(core::iter::once(model.residual(&data)))
+                    .value;
+            }
+            let evaluated = ix + 1;
+            if evaluated % block_size == 0 {
+                // The first block was already evaluated by the SPRT while generating the hypotheses.
+                let keep = kept_hypotheses(candidates, evaluated / block_size - 1);
+                if keep < hypotheses.len() {
+                    hypotheses.select_nth_unstable_by(keep, |a, b| b.1.total_cmp(&a.1));
+                    hypotheses.truncate(keep);
+                }
+            }
+        }
+        report.model = hypotheses
+            .into_iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(model, _)| model);
+        report
+    }
+}
+
+impl<E, R, S, C, T, Data> Consensus<E, Data> for Arrsac<R, S, C, T>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    S: Sampler,
+    C: Scorer,
+    T: Termination,
+{
+    type Inliers = Vec<usize>;
+
+    fn model<I>(&mut self, estimator: &E, data: I) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.search(estimator, data).model
+    }
+
+    fn model_inliers<I>(&mut self, estimator: &E, data: I) -> Option<(E::Model, Self::Inliers)>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let report = self.model_report(estimator, data);
+        Some((report.model?, report.inliers))
+    }
+}
+
+impl<E, R, S, C, T, Data> ReportingConsensus<E, Data> for Arrsac<R, S, C, T>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    S: Sampler,
+    C: Scorer,
+    T: Termination,
+{
+    fn model_report<I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let mut report = self.search(estimator, data.clone());
+        if let Some(model) = &report.model {
+            let len = data.clone().count();
+            report.inliers = self
+                .scorer
+                .inliers(data.clone().map(|data| model.residual(&data)));
+            // The winner may not have been evaluated on all of the data, so score it on all of it.
+            report.score = Some(
+                self.scorer
+                    .score(data.map(|data| model.residual(&data)))
+                    .value,
+            );
+            report.confidence = achieved_confidence(
+                report.inliers.len() as f64 / len as f64,
+                E::MIN_SAMPLES,
+                report.iterations,
+            );
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{noisy_line, LineEstimator, LINE};
+    use crate::{Cancellation, MsacScorer};
+    use core::cell::Cell;
+    use rand_core::SeedableRng;
+    use rand_pcg::Pcg64;
+
+    /// A model which fits every datapoint and counts how often it is evaluated past the first block.
+    struct Everything<'a> {
+        block_size: usize,
+        residuals: &'a Cell<usize>,
+    }
+
+    impl Model<usize> for Everything<'_> {
+        fn residual(&self, &data: &usize) -> f64 {
+            if data >= self.block_size {
+                self.residuals.set(self.residuals.get() + 1);
+            }
+            0.0
+        }
+    }
+
+    /// Estimates two models from every sample.
+    struct Twice<'a> {
+        block_size: usize,
+        residuals: &'a Cell<usize>,
+    }
+
+    impl<'a> Estimator<usize> for Twice<'a> {
+        type Model = Everything<'a>;
+        type ModelIter = [Everything<'a>; 2];
+        const MIN_SAMPLES: usize = 1;
+
+        fn estimate<I>(&self, _data: I) -> Self::ModelIter
+        where
+            I: Iterator<Item = usize> + Clone,
+        {
+            [(); 2].map(|()| Everything {
+                block_size: self.block_size,
+                residuals: self.residuals,
+            })
+        }
+    }
+
+    #[test]
+    fn search_halves_the_hypotheses_after_every_block_past_the_first() {
+        for block_size in [2, 5] {
+            let residuals = Cell::new(0);
+            let estimator = Twice {
+                block_size,
+                residuals: &residuals,
+            };
+            // The first sample already has only inliers, so 2 hypotheses are generated from it and 14 more
+            // from 7 samples of its inliers.
+            let report = Arrsac::new(1.0, Pcg64::seed_from_u64(0))
+                .block_size(block_size)
+                .inner_hypotheses(7)
+                .model_report(&estimator, 0..100);
+            assert_eq!(report.hypotheses, 16);
+            assert_eq!(report.rejected_hypotheses, 0);
+            // Finding the inliers of the winner and scoring it on all of the data adds two evaluations of every
+            // datapoint past the first block.
+            let scored = 2 * (100 - block_size);
+            assert_eq!(residuals.get() - scored, block_size * (16 + 8 + 4 + 2));
+        }
+    }
+
+    #[test]
+    fn cancellation_interrupts_the_evaluation() {
+        let residuals = Cell::new(0);
+        let estimator = Twice {
+            block_size: 2,
+            residuals: &residuals,
+        };
+        // Cancel once the 16 hypotheses have been evaluated on the second block.
+        let report = Arrsac::new(1.0, Pcg64::seed_from_u64(0))
+            .block_size(2)
+            .inner_hypotheses(7)
+            .termination(Cancellation::new(|| residuals.get() >= 32))
+            .model_report(&estimator, 0..100);
+        assert_eq!(report.termination, TerminationReason::Cancelled);
+        assert!(report.model.is_some());
+        assert_eq!(residuals.get() - 2 * 98, 32);
+    }
+
+    #[test]
+    fn termination_stops_the_generation() {
+        let data = noisy_line(0, 60, 140);
+        let estimator = LineEstimator::default();
+        let full = Arrsac::new(0.2, Pcg64::seed_from_u64(0))
+            .model_report(&estimator, data.iter().copied());
+        let report = Arrsac::new(0.2, Pcg64::seed_from_u64(0))
+            .termination(MaxIterations::new(5))
+            .model_report(&estimator, data.iter().copied());
+        assert_eq!(report.termination, TerminationReason::MaxIterations);
+        assert!(report.iterations >= 5);
+        assert!(report.iterations < full.iterations);
+        let model = report.model.unwrap();
+        assert!((model.slope - LINE.slope).abs() < 0.1);
+    }
+
+    #[test]
+    fn search_scores_with_the_scorer() {
+        let data = noisy_line(0, 60, 140);
+        let mut scorer = MsacScorer::new(0.2);
+        let report = Arrsac::new(0.2, Pcg64::seed_from_u64(0))
+            .scorer(scorer)
+            .model_report(&LineEstimator::default(), data.iter().copied());
+        let model = report.model.unwrap();
+        assert!((model.slope - LINE.slope).abs() < 0.1);
+        let residuals = data.iter().map(|data| model.residual(data));
+        assert_eq!(report.score, Some(scorer.score(residuals.clone()).value));
+        assert_eq!(report.inliers, scorer.inliers(residuals));
+    }
+}
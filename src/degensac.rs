@@ -0,0 +1,305 @@
+use crate::search::Search;
+use crate::{
+    AcceptAll, Adaptive, Consensus, ConsensusReport, CountScorer, Estimator, Model,
+    ReportingConsensus, Sampler, Scorer, Sprt, Termination, Uniform,
+};
+use alloc::vec::Vec;
+use rand_core::RngCore;
+
+/// A `Degeneracy` detects models estimated from a degenerate sample, and can attempt to recover the correct model.
+///
+/// Some degenerate samples still produce a model with high support. The classic example is a fundamental matrix
+/// estimated from 7 correspondences of which 5 or more lie on a dominant plane: every correspondence on the plane
+/// supports the model, even though it is wrong for the rest of the scene. The degeneracy can be detected from the
+/// sample, such as by a homography which is consistent with too many of its correspondences, and the correct model
+/// can often be recovered from the rest of the data, such as with plane-and-parallax.
+pub trait Degeneracy<E, Data>
+where
+    E: Estimator<Data>,
+{
+    /// Returns `true` if `model`, estimated from the datapoints of `sample`, is degenerate.
+    fn is_degenerate<I>(&mut self, model: &E::Model, sample: I) -> bool
+    where
+        I: Iterator<Item = Data> + Clone;
+
+    /// Attempts to recover a non-degenerate model from the degenerate `model` estimated from the datapoints of
+    /// `sample`, using all of the `data` and the inlier `threshold`.
+    ///
+    /// Returns `None` if no model could be recovered. The recovered model is only used by the consensus if
+    /// it is better than `model`, so it is fine to return a model which turns out to be worse.
+    ///
+    /// By default no recovery is attempted.
+    fn recover<S, I, R>(
+        &mut self,
+        _estimator: &E,
+        _model: &E::Model,
+        _sample: S,
+        _data: I,
+        _threshold: f64,
+        _rng: &mut R,
+    ) -> Option<E::Model>
+    where
+        S: Iterator<Item = Data> + Clone,
+        I: Iterator<Item = Data> + Clone,
+        R: RngCore,
+    {
+        None
+    }
+}
+
+/// The DEGENSAC algorithm from the paper "Two-View Geometry Estimation Unaffected by a Dominant Plane"
+/// by Chum et al.
+///
+/// This samples, scores, and terminates exactly like [`Ransac`](crate::Ransac), but whenever a sample produces
+/// a new best hypothesis, it is tested by a [`Degeneracy`]. If it is degenerate, the degeneracy attempts to recover
+/// the correct model, which replaces the hypothesis if it scores higher. Only testing new best hypotheses keeps
+/// the overhead low, since they are rare. The hypotheses can be scored by any [`Scorer`], while recovery keeps
+/// using the inlier threshold.
+#[derive(Clone, Debug)]
+pub struct Degensac<R, D, S = Uniform, C = CountScorer, T = Adaptive> {
+    threshold: f64,
+    scorer: C,
+    termination: T,
+    sprt: Option<Sprt>,
+    rng: R,
+    sampler: S,
+    rejected_samples: usize,
+    degeneracy: D,
+    degenerate_hypotheses: usize,
+}
+
+impl<R, D> Degensac<R, D>
+where
+    R: RngCore,
+{
+    /// Creates a new `Degensac` which considers a datapoint an inlier when its residual is below `threshold`,
+    /// and tests new best hypotheses with `degeneracy`.
+    ///
+    /// `rng` is used to draw the samples. By default, at most `1000` iterations are run and iteration stops
+    /// early once an all-inlier sample has been drawn with a confidence of `0.99`.
+    pub fn new(threshold: f64, degeneracy: D, rng: R) -> Self {
+        Self {
+            threshold,
+            scorer: CountScorer::new(threshold),
+            termination: Adaptive::new(0.99, 1000),
+            sprt: None,
+            rng,
+            sampler: Uniform,
+            rejected_samples: 0,
+            degeneracy,
+            degenerate_hypotheses: 0,
+        }
+    }
+}
+
+impl<R, D, S, C> Degensac<R, D, S, C>
+where
+    R: RngCore,
+{
+    /// The maximum number of samples to draw.
+    ///
+    /// Default: `1000`
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            termination: self.termination.max_iterations(max_iterations),
+            ..self
+        }
+    }
+
+    /// The probability of having drawn at least one all-inlier sample at which iteration stops.
+    ///
+    /// Default: `0.99`
+    pub fn confidence(self, confidence: f64) -> Self {
+        Self {
+            termination: self.termination.confidence(confidence),
+            ..self
+        }
+    }
+}
+
+impl<R, D, S, T> Degensac<R, D, S, CountScorer, T>
+where
+    R: RngCore,
+{
+    /// The residual below which a datapoint is considered an inlier.
+    ///
+    /// Default: specified in [`Degensac::new`]
+    pub fn threshold(self, threshold: f64) -> Self {
+        Self {
+            threshold,
+            scorer: CountScorer::new(threshold),
+            ..self
+        }
+    }
+}
+
+impl<R, D, S, C, T> Degensac<R, D, S, C, T>
+where
+    R: RngCore,
+{
+    /// Verifies hypotheses with the [`Sprt`] so that bad ones are rejected early.
+    ///
+    /// Default: every hypothesis is evaluated on all of the data
+    pub fn sprt(self, sprt: Sprt) -> Self {
+        Self {
+            sprt: Some(sprt),
+            ..self
+        }
+    }
+
+    /// The [`Sampler`] which draws the minimal samples.
+    ///
+    /// Default: [`Uniform`]
+    pub fn sampler<X>(self, sampler: X) -> Degensac<R, D, X, C, T> {
+        Degensac {
+            threshold: self.threshold,
+            scorer: self.scorer,
+            termination: self.termination,
+            sprt: self.sprt,
+            rng: self.rng,
+            sampler,
+            rejected_samples: self.rejected_samples,
+            degeneracy: self.degeneracy,
+            degenerate_hypotheses: self.degenerate_hypotheses,
+        }
+    }
+
+    /// The [`Termination`] criterion which decides when to stop drawing samples, instead of the maximum number
+    /// of iterations and the confidence.
+    ///
+    /// Default: [`Adaptive`] with a confidence of `0.99` and at most `1000` iterations
+    pub fn termination<X>(self, termination: X) -> Degensac<R, D, S, C, X> {
+        Degensac {
+            threshold: self.threshold,
+            scorer: self.scorer,
+            termination,
+            sprt: self.sprt,
+            rng: self.rng,
+            sampler: self.sampler,
+            rejected_samples: self.rejected_samples,
+            degeneracy: self.degeneracy,
+            degenerate_hypotheses: self.degenerate_hypotheses,
+        }
+    }
+
+    /// The [`Scorer`] which scores the hypotheses, while recovery keeps using the inlier threshold.
+    ///
+    /// Default: [`CountScorer`] with the threshold specified in [`Degensac::new`]
+    pub fn scorer<X>(self, scorer: X) -> Degensac<R, D, S, X, T> {
+        Degensac {
+            threshold: self.threshold,
+            scorer,
+            termination: self.termination,
+            sprt: self.sprt,
+            rng: self.rng,
+            sampler: self.sampler,
+            rejected_samples: self.rejected_samples,
+            degeneracy: self.degeneracy,
+            degenerate_hypotheses: self.degenerate_hypotheses,
+        }
+    }
+
+    /// The number of minimal samples rejected by [`Estimator::is_sample_valid`] during the last search.
+    pub fn rejected_samples(&self) -> usize {
+        self.rejected_samples
+    }
+
+    /// The number of new best hypotheses found to be degenerate during the last search.
+    pub fn degenerate_hypotheses(&self) -> usize {
+        self.degenerate_hypotheses
+    }
+}
+
+impl<R, D, S, C, T> Degensac<R, D, S, C, T>
+where
+    R: RngCore,
+    S: Sampler,
+    C: Scorer,
+    T: Termination,
+{
+    /// Runs the search and reports the best model, without its inliers.
+    fn search<E, Data, I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        E: Estimator<Data>,
+        D: Degeneracy<E, Data>,
+        I: Iterator<Item = Data> + Clone,
+    {
+        let threshold = self.threshold;
+        let scorer = &mut self.scorer;
+        let degeneracy = &mut self.degeneracy;
+        let degenerate_hypotheses = &mut self.degenerate_hypotheses;
+        *degenerate_hypotheses = 0;
+        Search {
+            rng: &mut self.rng,
+            sampler: &mut self.sampler,
+            sample_check: &mut AcceptAll,
+            model_check: &mut AcceptAll,
+            verifier: &mut self.sprt,
+            rejected_samples: &mut self.rejected_samples,
+            termination: &mut self.termination,
+        }
+        .run_optimized(
+            estimator,
+            data,
+            |model, data| {
+                let score = scorer.score(data.map(|data| model.residual(&data)));
+                (score.value, score.inliers)
+            },
+            |rng, _, model, sample, data| {
+                if !degeneracy.is_degenerate(model, sample.clone()) {
+                    return None;
+                }
+                *degenerate_hypotheses += 1;
+                degeneracy.recover(estimator, model, sample, data, threshold, rng)
+            },
+        )
+    }
+}
+
+impl<E, R, D, S, C, T, Data> Consensus<E, Data> for Degensac<R, D, S, C, T>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    D: Degeneracy<E, Data>,
+    S: Sampler,
+    C: Scorer,
+    T: Termination,
+{
+    type Inliers = Vec<usize>;
+
+    fn model<I>(&mut self, estimator: &E, data: I) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.search(estimator, data).model
+    }
+
+    fn model_inliers<I>(&mut self, estimator: &E, data: I) -> Option<(E::Model, Self::Inliers)>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let report = self.model_report(estimator, data);
+        Some((report.model?, report.inliers))
+    }
+}
+
+impl<E, R, D, S, C, T, Data> ReportingConsensus<E, Data> for Degensac<R, D, S, C, T>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    D: Degeneracy<E, Data>,
+    S: Sampler,
+    C: Scorer,
+    T: Termination,
+{
+    fn model_report<I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let mut report = self.search(estimator, data.clone());
+        if let Some(model) = &report.model {
+            report.inliers = self.scorer.inliers(data.map(|data| model.residual(&data)));
+        }
+        report
+    }
+}
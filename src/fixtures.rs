@@ -0,0 +1,125 @@
+//! Data and estimators shared by the tests.
+
+use crate::{Estimator, Model, NonMinimalEstimator, WeightedEstimator};
+use alloc::vec::Vec;
+use core::cell::Cell;
+use rand_core::{RngCore, SeedableRng};
+use rand_pcg::Pcg64;
+
+/// The line `y = slope * x + intercept`, whose residual is the vertical distance of a point from it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct Line {
+    pub(crate) slope: f64,
+    pub(crate) intercept: f64,
+}
+
+impl Model<[f64; 2]> for Line {
+    fn residual(&self, &[x, y]: &[f64; 2]) -> f64 {
+        libm::fabs(y - (self.slope * x + self.intercept))
+    }
+}
+
+/// Estimates lines through two points and fits lines to more points with least squares, counting how many
+/// times it does either.
+#[derive(Debug, Default)]
+pub(crate) struct LineEstimator {
+    pub(crate) estimates: Cell<usize>,
+    pub(crate) fits: Cell<usize>,
+}
+
+impl Estimator<[f64; 2]> for LineEstimator {
+    type Model = Line;
+    type ModelIter = Option<Line>;
+    const MIN_SAMPLES: usize = 2;
+
+    fn estimate<I>(&self, mut data: I) -> Self::ModelIter
+    where
+        I: Iterator<Item = [f64; 2]> + Clone,
+    {
+        self.estimates.set(self.estimates.get() + 1);
+        let ([x0, y0], [x1, y1]) = (data.next()?, data.next()?);
+        if x0 == x1 {
+            return None;
+        }
+        let slope = (y1 - y0) / (x1 - x0);
+        Some(Line {
+            slope,
+            intercept: y0 - slope * x0,
+        })
+    }
+}
+
+impl NonMinimalEstimator<[f64; 2]> for LineEstimator {
+    fn estimate_non_minimal<I>(&self, data: I) -> Option<Line>
+    where
+        I: Iterator<Item = [f64; 2]> + Clone,
+    {
+        self.estimate_weighted(data.map(|data| (data, 1.0)))
+    }
+}
+
+impl WeightedEstimator<[f64; 2]> for LineEstimator {
+    fn estimate_weighted<I>(&self, data: I) -> Option<Line>
+    where
+        I: Iterator<Item = ([f64; 2], f64)> + Clone,
+    {
+        self.fits.set(self.fits.get() + 1);
+        let (mut w, mut x, mut y, mut xx, mut xy) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for ([px, py], weight) in data {
+            w += weight;
+            x += weight * px;
+            y += weight * py;
+            xx += weight * px * px;
+            xy += weight * px * py;
+        }
+        let denominator = w * xx - x * x;
+        if denominator.is_nan() || denominator <= 1e-12 {
+            return None;
+        }
+        let slope = (w * xy - x * y) / denominator;
+        Some(Line {
+            slope,
+            intercept: (y - slope * x) / w,
+        })
+    }
+}
+
+/// The line the points from [`noisy_line`] are drawn around.
+pub(crate) const LINE: Line = Line {
+    slope: 2.0,
+    intercept: 1.0,
+};
+
+/// A random number generator seeded with `seed`.
+pub(crate) fn rng(seed: u64) -> Pcg64 {
+    Pcg64::seed_from_u64(seed)
+}
+
+/// Draws a number uniformly at random from `low..high`.
+pub(crate) fn uniform<R>(rng: &mut R, low: f64, high: f64) -> f64
+where
+    R: RngCore,
+{
+    low + (high - low) * (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Shuffled points of which `inliers` are within `0.1` of [`LINE`] and `outliers` are anywhere in the plane,
+/// with `x` in `0..10`.
+pub(crate) fn noisy_line(seed: u64, inliers: usize, outliers: usize) -> Vec<[f64; 2]> {
+    let mut rng = rng(seed);
+    let mut points = Vec::with_capacity(inliers + outliers);
+    for _ in 0..inliers {
+        let x = uniform(&mut rng, 0.0, 10.0);
+        let noise = uniform(&mut rng, -0.1, 0.1);
+        points.push([x, LINE.slope * x + LINE.intercept + noise]);
+    }
+    for _ in 0..outliers {
+        let x = uniform(&mut rng, 0.0, 10.0);
+        points.push([x, uniform(&mut rng, -50.0, 50.0)]);
+    }
+    for ix in (1..points.len()).rev() {
+        let other = (rng.next_u64() % (ix as u64 + 1)) as usize;
+        points.swap(ix, other);
+    }
+    points
+}
@@ -0,0 +1,150 @@
+const EPSILON: f64 = 1e-15;
+const MAX_ITERATIONS: usize = 500;
+
+/// The regularized lower incomplete gamma function `P(a, x)` for `a > 0`.
+pub(crate) fn lower_regularized(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        0.0
+    } else if x < a + 1.0 {
+        series(a, x)
+    } else {
+        1.0 - continued_fraction(a, x)
+    }
+}
+
+/// The regularized upper incomplete gamma function `Q(a, x)` for `a > 0`.
+pub(crate) fn upper_regularized(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        1.0
+    } else if x < a + 1.0 {
+        1.0 - series(a, x)
+    } else {
+        continued_fraction(a, x)
+    }
+}
+
+/// The lower incomplete gamma function `γ(a, x)` for `a > 0`.
+pub(crate) fn lower(a: f64, x: f64) -> f64 {
+    libm::tgamma(a) * lower_regularized(a, x)
+}
+
+/// The upper incomplete gamma function `Γ(a, x)` for `a > 0`.
+pub(crate) fn upper(a: f64, x: f64) -> f64 {
+    libm::tgamma(a) * upper_regularized(a, x)
+}
+
+/// The square root of the quantile of the chi-squared distribution with `dof` degrees of freedom at `p`.
+///
+/// This is the multiple of the standard deviation which contains a fraction `p` of normally distributed
+/// residuals with `dof` dimensions.
+pub(crate) fn chi_quantile(dof: f64, p: f64) -> f64 {
+    let (mut low, mut high) = (0.0, 1.0);
+    while lower_regularized(0.5 * dof, 0.5 * high) < p {
+        high *= 2.0;
+    }
+    for _ in 0..100 {
+        let mid = 0.5 * (low + high);
+        if lower_regularized(0.5 * dof, 0.5 * mid) < p {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    libm::sqrt(0.5 * (low + high))
+}
+
+/// `P(a, x)` by its series expansion, which converges quickly for `x < a + 1`.
+fn series(a: f64, x: f64) -> f64 {
+    let mut term = 1.0 / a;
+    let mut sum = term;
+    let mut n = a;
+    for _ in 0..MAX_ITERATIONS {
+        n += 1.0;
+        term *= x / n;
+        sum += term;
+        if libm::fabs(term) < libm::fabs(sum) * EPSILON {
+            break;
+        }
+    }
+    sum * libm::exp(-x + a * libm::log(x) - libm::lgamma(a))
+}
+
+/// `Q(a, x)` by its continued fraction expansion using the modified Lentz method, which converges
+/// quickly for `x >= a + 1`.
+fn continued_fraction(a: f64, x: f64) -> f64 {
+    let tiny = f64::MIN_POSITIVE / EPSILON;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..=MAX_ITERATIONS {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        if libm::fabs(d) < tiny {
+            d = tiny;
+        }
+        c = b + an / c;
+        if libm::fabs(c) < tiny {
+            c = tiny;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if libm::fabs(delta - 1.0) < EPSILON {
+            break;
+        }
+    }
+    libm::exp(-x + a * libm::log(x) - libm::lgamma(a)) * h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            libm::fabs(actual - expected) < 1e-12,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn regularized_matches_closed_forms() {
+        // P(1, x) = 1 - exp(-x)
+        assert_close(lower_regularized(1.0, 1.0), 1.0 - libm::exp(-1.0));
+        assert_close(upper_regularized(1.0, 1.0), libm::exp(-1.0));
+        assert_close(upper_regularized(1.0, 5.0), libm::exp(-5.0));
+        // P(1/2, x) = erf(sqrt(x)), on both sides of x = a + 1.
+        assert_close(lower_regularized(0.5, 1.0), libm::erf(1.0));
+        assert_close(lower_regularized(0.5, 4.0), libm::erf(2.0));
+        assert_close(upper_regularized(0.5, 4.0), libm::erfc(2.0));
+    }
+
+    #[test]
+    fn regularized_at_zero() {
+        assert_eq!(lower_regularized(1.5, 0.0), 0.0);
+        assert_eq!(upper_regularized(1.5, 0.0), 1.0);
+    }
+
+    #[test]
+    fn unregularized_matches_closed_forms() {
+        // γ(2, x) = 1 - (1 + x) exp(-x)
+        assert_close(lower(2.0, 3.0), 1.0 - 4.0 * libm::exp(-3.0));
+        // Γ(3, x) = 2 exp(-x) (1 + x + x² / 2)
+        assert_close(upper(3.0, 5.0), 37.0 * libm::exp(-5.0));
+        assert_close(lower(3.0, 5.0) + upper(3.0, 5.0), 2.0);
+    }
+
+    #[test]
+    fn chi_quantile_matches_known_values() {
+        // One standard deviation contains 68.27% of one dimensional normal residuals.
+        assert!(libm::fabs(chi_quantile(1.0, libm::erf(libm::sqrt(0.5))) - 1.0) < 1e-9);
+        // The chi-squared distribution with two degrees of freedom is exponential.
+        assert!(libm::fabs(chi_quantile(2.0, 0.99) - libm::sqrt(-2.0 * libm::log(0.01))) < 1e-9);
+        // The 0.99 quantile of the chi-squared distribution with four degrees of freedom is 13.2767.
+        assert!(libm::fabs(chi_quantile(4.0, 0.99) - libm::sqrt(13.276704135987622)) < 1e-9);
+    }
+}
@@ -0,0 +1,473 @@
+use crate::maxflow::MaxFlow;
+use crate::sample::Select;
+use crate::search::{count_inliers, Search};
+use crate::{
+    AcceptAll, Adaptive, Consensus, ConsensusReport, CountScorer, Model, Neighborhood,
+    NonMinimalEstimator, Refiner, ReportingConsensus, Sampler, Scorer, Sprt, Termination, Uniform,
+};
+use alloc::vec::Vec;
+use rand_core::RngCore;
+
+/// A [`Refiner`] which labels inliers with a graph cut, as in the paper "Graph-Cut RANSAC" by Barath and Matas.
+///
+/// Inliers tend to be spatially coherent, so the neighbors of an inlier are likely inliers as well. The labeling
+/// minimizes an energy in which every datapoint costs `residual^2 / threshold^2` as an inlier or `1` as an outlier,
+/// and every pair of neighbors with different labels costs the spatial coherence weight, or half of it when only one
+/// of them lists the other as a neighbor. The minimum is found exactly with a minimum cut. The model is then
+/// re-estimated from the labeled inliers until it stops improving.
+///
+/// The model is re-estimated with [`NonMinimalEstimator::estimate_non_minimal`].
+#[derive(Clone, Debug)]
+pub struct GraphCut<N> {
+    neighborhood: N,
+    spatial_coherence: f64,
+    iterations: usize,
+    flow: MaxFlow,
+    labels: Vec<usize>,
+}
+
+impl<N> GraphCut<N>
+where
+    N: Neighborhood,
+{
+    /// Creates a new `GraphCut` which considers datapoints to be spatially coherent with their neighbors
+    /// in `neighborhood`.
+    pub fn new(neighborhood: N) -> Self {
+        Self {
+            neighborhood,
+            spatial_coherence: 0.1,
+            iterations: 10,
+            flow: MaxFlow::default(),
+            labels: Vec::new(),
+        }
+    }
+
+    /// The cost of a pair of neighbors having different labels. Higher values produce more spatially
+    /// coherent labelings, while `0.0` labels the same inliers as a threshold.
+    ///
+    /// Default: `0.1`
+    pub fn spatial_coherence(self, spatial_coherence: f64) -> Self {
+        Self {
+            spatial_coherence,
+            ..self
+        }
+    }
+
+    /// The maximum number of times the model is re-estimated from the labeled inliers.
+    ///
+    /// Default: `10`
+    pub fn iterations(self, iterations: usize) -> Self {
+        Self { iterations, ..self }
+    }
+
+    /// Labels the inliers of `model` with a graph cut and returns their indices in increasing order.
+    pub fn label<M, Data, I>(&mut self, model: &M, data: I, threshold: f64) -> &[usize]
+    where
+        M: Model<Data>,
+        I: Iterator<Item = Data> + Clone,
+    {
+        let len = data.clone().count();
+        let (source, sink) = (len, len + 1);
+        self.flow.reset(len + 2);
+        for (ix, data) in data.enumerate() {
+            let residual = model.residual(&data) / threshold;
+            let inlier_cost = residual * residual;
+            let outlier_cost = 1.0;
+            // Only the difference between the costs of the labels matters.
+            if inlier_cost < outlier_cost {
+                self.flow
+                    .add_edge(source, ix, outlier_cost - inlier_cost, 0.0);
+            } else {
+                self.flow
+                    .add_edge(ix, sink, inlier_cost - outlier_cost, 0.0);
+            }
+            // Symmetric neighbors add this edge from both ends, so each end adds half of the weight.
+            let weight = 0.5 * self.spatial_coherence;
+            for &neighbor in self.neighborhood.neighbors(ix) {
+                if neighbor < len && neighbor != ix {
+                    self.flow.add_edge(ix, neighbor, weight, weight);
+                }
+            }
+        }
+        self.flow.solve(source, sink);
+        let flow = &self.flow;
+        self.labels.clear();
+        self.labels
+            .extend((0..len).filter(|&ix| flow.is_source_side(ix)));
+        &self.labels
+    }
+}
+
+impl<E, N, Data> Refiner<E, Data> for GraphCut<N>
+where
+    E: NonMinimalEstimator<Data>,
+    N: Neighborhood,
+{
+    fn refine<I, R, T>(
+        &mut self,
+        estimator: &E,
+        model: &E::Model,
+        data: I,
+        threshold: f64,
+        _rng: &mut R,
+        termination: &mut T,
+    ) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+        R: RngCore,
+        T: Termination,
+    {
+        let mut best: Option<(E::Model, usize)> = None;
+        for _ in 0..self.iterations {
+            if termination.is_interrupted() {
+                break;
+            }
+            let current = best.as_ref().map_or(model, |(model, _)| model);
+            let labels = self.label(current, data.clone(), threshold);
+            if labels.len() < E::MIN_SAMPLES {
+                break;
+            }
+            let estimated = estimator
+                .estimate_non_minimal(Select::new(data.clone(), labels))
+                .map(|model| {
+                    let inliers = count_inliers(&model, data.clone(), threshold);
+                    (model, inliers)
+                });
+            match estimated {
+                Some(estimated) if best.as_ref().is_none_or(|best| estimated.1 > best.1) => {
+                    best = Some(estimated)
+                }
+                _ => break,
+            }
+        }
+        best.map(|(model, _)| model)
+    }
+}
+
+/// The GC-RANSAC (graph-cut RANSAC) algorithm.
+///
+/// This is [`LoRansac`](crate::LoRansac) with the [`GraphCut`] refiner, except that the inliers returned by
+/// [`Consensus::model_inliers`] are labeled with a graph cut rather than a threshold, so they are spatially coherent.
+/// The hypotheses can be scored by any [`Scorer`], while the graph cut keeps using the inlier threshold.
+#[derive(Clone, Debug)]
+pub struct GcRansac<R, N, S = Uniform, C = CountScorer, T = Adaptive> {
+    threshold: f64,
+    scorer: C,
+    termination: T,
+    sprt: Option<Sprt>,
+    rng: R,
+    sampler: S,
+    rejected_samples: usize,
+    graph_cut: GraphCut<N>,
+}
+
+impl<R, N> GcRansac<R, N>
+where
+    R: RngCore,
+    N: Neighborhood,
+{
+    /// Creates a new `GcRansac` which considers a datapoint an inlier when its residual is below `threshold`,
+    /// and considers datapoints to be spatially coherent with their neighbors in `neighborhood`.
+    ///
+    /// `rng` is used to draw the samples. By default, at most `1000` iterations are run and iteration stops
+    /// early once an all-inlier sample has been drawn with a confidence of `0.99`.
+    pub fn new(threshold: f64, neighborhood: N, rng: R) -> Self {
+        Self {
+            threshold,
+            scorer: CountScorer::new(threshold),
+            termination: Adaptive::new(0.99, 1000),
+            sprt: None,
+            rng,
+            sampler: Uniform,
+            rejected_samples: 0,
+            graph_cut: GraphCut::new(neighborhood),
+        }
+    }
+}
+
+impl<R, N, S, C> GcRansac<R, N, S, C>
+where
+    R: RngCore,
+    N: Neighborhood,
+{
+    /// The maximum number of samples to draw.
+    ///
+    /// Default: `1000`
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            termination: self.termination.max_iterations(max_iterations),
+            ..self
+        }
+    }
+
+    /// The probability of having drawn at least one all-inlier sample at which iteration stops.
+    ///
+    /// Default: `0.99`
+    pub fn confidence(self, confidence: f64) -> Self {
+        Self {
+            termination: self.termination.confidence(confidence),
+            ..self
+        }
+    }
+}
+
+impl<R, N, S, T> GcRansac<R, N, S, CountScorer, T>
+where
+    R: RngCore,
+    N: Neighborhood,
+{
+    /// The residual below which a datapoint is considered an inlier.
+    ///
+    /// Default: specified in [`GcRansac::new`]
+    pub fn threshold(self, threshold: f64) -> Self {
+        Self {
+            threshold,
+            scorer: CountScorer::new(threshold),
+            ..self
+        }
+    }
+}
+
+impl<R, N, S, C, T> GcRansac<R, N, S, C, T>
+where
+    R: RngCore,
+    N: Neighborhood,
+{
+    /// Verifies hypotheses with the [`Sprt`] so that bad ones are rejected early.
+    ///
+    /// Default: every hypothesis is evaluated on all of the data
+    pub fn sprt(self, sprt: Sprt) -> Self {
+        Self {
+            sprt: Some(sprt),
+            ..self
+        }
+    }
+
+    /// The cost of a pair of neighbors having different labels. See [`GraphCut::spatial_coherence`].
+    ///
+    /// Default: `0.1`
+    pub fn spatial_coherence(self, spatial_coherence: f64) -> Self {
+        Self {
+            graph_cut: self.graph_cut.spatial_coherence(spatial_coherence),
+            ..self
+        }
+    }
+
+    /// The maximum number of times a new best model is re-estimated from its labeled inliers.
+    ///
+    /// Default: `10`
+    pub fn refinement_iterations(self, iterations: usize) -> Self {
+        Self {
+            graph_cut: self.graph_cut.iterations(iterations),
+            ..self
+        }
+    }
+
+    /// The [`Sampler`] which draws the minimal samples.
+    ///
+    /// Default: [`Uniform`]
+    pub fn sampler<X>(self, sampler: X) -> GcRansac<R, N, X, C, T> {
+        GcRansac {
+            threshold: self.threshold,
+            scorer: self.scorer,
+            termination: self.termination,
+            sprt: self.sprt,
+            rng: self.rng,
+            sampler,
+            rejected_samples: self.rejected_samples,
+            graph_cut: self.graph_cut,
+        }
+    }
+
+    /// The [`Termination`] criterion which decides when to stop drawing samples, instead of the maximum number
+    /// of iterations and the confidence.
+    ///
+    /// Default: [`Adaptive`] with a confidence of `0.99` and at most `1000` iterations
+    pub fn termination<X>(self, termination: X) -> GcRansac<R, N, S, C, X> {
+        GcRansac {
+            threshold: self.threshold,
+            scorer: self.scorer,
+            termination,
+            sprt: self.sprt,
+            rng: self.rng,
+            sampler: self.sampler,
+            rejected_samples: self.rejected_samples,
+            graph_cut: self.graph_cut,
+        }
+    }
+
+    /// The [`Scorer`] which scores the hypotheses, while the graph cut keeps using the inlier threshold.
+    ///
+    /// Default: [`CountScorer`] with the threshold specified in [`GcRansac::new`]
+    pub fn scorer<D>(self, scorer: D) -> GcRansac<R, N, S, D, T> {
+        GcRansac {
+            threshold: self.threshold,
+            scorer,
+            termination: self.termination,
+            sprt: self.sprt,
+            rng: self.rng,
+            sampler: self.sampler,
+            rejected_samples: self.rejected_samples,
+            graph_cut: self.graph_cut,
+        }
+    }
+
+    /// The number of minimal samples rejected by
+    /// [`Estimator::is_sample_valid`](crate::Estimator::is_sample_valid) during the last search.
+    pub fn rejected_samples(&self) -> usize {
+        self.rejected_samples
+    }
+}
+
+impl<R, N, S, C, T> GcRansac<R, N, S, C, T>
+where
+    R: RngCore,
+    N: Neighborhood,
+    S: Sampler,
+    C: Scorer,
+    T: Termination,
+{
+    /// Runs the search and reports the best model, without its inliers.
+    fn search<E, Data, I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        E: NonMinimalEstimator<Data>,
+        I: Iterator<Item = Data> + Clone,
+    {
+        let threshold = self.threshold;
+        let scorer = &mut self.scorer;
+        let graph_cut = &mut self.graph_cut;
+        Search {
+            rng: &mut self.rng,
+            sampler: &mut self.sampler,
+            sample_check: &mut AcceptAll,
+            model_check: &mut AcceptAll,
+            verifier: &mut self.sprt,
+            rejected_samples: &mut self.rejected_samples,
+            termination: &mut self.termination,
+        }
+        .run_optimized(
+            estimator,
+            data,
+            |model, data| {
+                let score = scorer.score(data.map(|data| model.residual(&data)));
+                (score.value, score.inliers)
+            },
+            |rng, termination, model, _, data| {
+                graph_cut.refine(estimator, model, data, threshold, rng, termination)
+            },
+        )
+    }
+}
+
+impl<E, R, N, S, C, T, Data> Consensus<E, Data> for GcRansac<R, N, S, C, T>
+where
+    E: NonMinimalEstimator<Data>,
+    R: RngCore,
+    N: Neighborhood,
+    S: Sampler,
+    C: Scorer,
+    T: Termination,
+{
+    type Inliers = Vec<usize>;
+
+    fn model<I>(&mut self, estimator: &E, data: I) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.search(estimator, data).model
+    }
+
+    fn model_inliers<I>(&mut self, estimator: &E, data: I) -> Option<(E::Model, Self::Inliers)>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let report = self.model_report(estimator, data);
+        Some((report.model?, report.inliers))
+    }
+}
+
+impl<E, R, N, S, C, T, Data> ReportingConsensus<E, Data> for GcRansac<R, N, S, C, T>
+where
+    E: NonMinimalEstimator<Data>,
+    R: RngCore,
+    N: Neighborhood,
+    S: Sampler,
+    C: Scorer,
+    T: Termination,
+{
+    fn model_report<I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let mut report = self.search(estimator, data.clone());
+        if let Some(model) = &report.model {
+            report.inliers = self.graph_cut.label(model, data, self.threshold).to_vec();
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::inliers;
+    use alloc::vec;
+
+    /// A model whose residual is the distance of a number from it.
+    struct Point(f64);
+
+    impl Model<f64> for Point {
+        fn residual(&self, data: &f64) -> f64 {
+            libm::fabs(data - self.0)
+        }
+    }
+
+    #[test]
+    fn label_without_spatial_coherence_is_a_threshold() {
+        let data = [0.0, 0.5, 1.5, -0.9, 3.0, 1.0, -2.0, 0.99];
+        let everyone: Vec<Vec<usize>> = (0..data.len())
+            .map(|ix| (0..data.len()).filter(|&neighbor| neighbor != ix).collect())
+            .collect();
+        let mut graph_cut = GraphCut::new(everyone).spatial_coherence(0.0);
+        let thresholded: Vec<usize> = inliers(&Point(0.0), data.iter().copied(), 1.0).collect();
+        assert_eq!(
+            graph_cut.label(&Point(0.0), data.iter().copied(), 1.0),
+            thresholded
+        );
+    }
+
+    #[test]
+    fn label_finds_the_minimum_energy() {
+        // The middle datapoint costs 1.1^2 - 1 = 0.21 more as an inlier, while labeling it an outlier separates
+        // it from both of its inlier neighbors, so it is an inlier once the spatial coherence exceeds 0.105.
+        let data = [0.0, 1.1, 0.0];
+        let neighbors = vec![vec![1], vec![0, 2], vec![1]];
+        let mut graph_cut = GraphCut::new(&neighbors).spatial_coherence(0.08);
+        assert_eq!(
+            graph_cut.label(&Point(0.0), data.iter().copied(), 1.0),
+            [0, 2]
+        );
+        let mut graph_cut = GraphCut::new(&neighbors).spatial_coherence(0.12);
+        assert_eq!(
+            graph_cut.label(&Point(0.0), data.iter().copied(), 1.0),
+            [0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn label_halves_one_sided_neighbors() {
+        // Only the middle datapoint lists its neighbors, so each pair costs half of the spatial coherence.
+        let data = [0.0, 1.1, 0.0];
+        let neighbors = vec![vec![], vec![0, 2], vec![]];
+        let mut graph_cut = GraphCut::new(&neighbors).spatial_coherence(0.2);
+        assert_eq!(
+            graph_cut.label(&Point(0.0), data.iter().copied(), 1.0),
+            [0, 2]
+        );
+        let mut graph_cut = GraphCut::new(&neighbors).spatial_coherence(0.22);
+        assert_eq!(
+            graph_cut.label(&Point(0.0), data.iter().copied(), 1.0),
+            [0, 1, 2]
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::{Model, Refiner, RobustKernel, Termination, WeightedEstimator};
+use alloc::vec::Vec;
+use rand_core::RngCore;
+
+/// Iteratively reweighted least squares (IRLS) with a [`RobustKernel`].
+///
+/// Starting from a model, such as the result of a consensus, every datapoint is weighted by the kernel according
+/// to its residual and the model is re-estimated from the weighted data with a [`WeightedEstimator`]. This repeats
+/// until no weight changes by more than the tolerance, or for at most the maximum number of iterations.
+///
+/// `Irls` is also a [`Refiner`], so it can be used for local optimization, where it ignores the inlier threshold
+/// in favor of the kernel.
+#[derive(Clone, Debug)]
+pub struct Irls<K> {
+    kernel: K,
+    max_iterations: usize,
+    tolerance: f64,
+}
+
+impl<K> Irls<K>
+where
+    K: RobustKernel,
+{
+    /// Creates a new `Irls` which weights the datapoints with `kernel`.
+    pub fn new(kernel: K) -> Self {
+        Self {
+            kernel,
+            max_iterations: 10,
+            tolerance: 1e-6,
+        }
+    }
+
+    /// The maximum number of times the model is re-estimated.
+    ///
+    /// Default: `10`
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            max_iterations,
+            ..self
+        }
+    }
+
+    /// Iteration stops once no weight changes by more than this.
+    ///
+    /// Default: `1e-6`
+    pub fn tolerance(self, tolerance: f64) -> Self {
+        Self { tolerance, ..self }
+    }
+
+    /// Refines `model` on `data` and returns the refined model along with the weight of every datapoint, in order,
+    /// according to the refined model.
+    ///
+    /// Returns `None` if the model could not be re-estimated even once.
+    pub fn refine_weighted<E, Data, I>(
+        &self,
+        estimator: &E,
+        model: &E::Model,
+        data: I,
+    ) -> Option<(E::Model, Vec<f64>)>
+    where
+        E: WeightedEstimator<Data>,
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.reweight(estimator, model, data, || false)
+    }
+
+    /// The same as [`Irls::refine_weighted`], but stops early once `is_interrupted` returns `true`.
+    fn reweight<E, Data, I>(
+        &self,
+        estimator: &E,
+        model: &E::Model,
+        data: I,
+        mut is_interrupted: impl FnMut() -> bool,
+    ) -> Option<(E::Model, Vec<f64>)>
+    where
+        E: WeightedEstimator<Data>,
+        I: Iterator<Item = Data> + Clone,
+    {
+        let mut weights: Vec<f64> = data
+            .clone()
+            .map(|data| self.kernel.weight(model.residual(&data)))
+            .collect();
+        let mut refined: Option<E::Model> = None;
+        for _ in 0..self.max_iterations {
+            if is_interrupted() {
+                break;
+            }
+            let weighted = data
+                .clone()
+                .zip(weights.iter().copied())
+                .filter(|&(_, weight)| weight > 0.0);
+            let Some(model) = estimator.estimate_weighted(weighted) else {
+                break;
+            };
+            let mut converged = true;
+            for (weight, data) in weights.iter_mut().zip(data.clone()) {
+                let new = self.kernel.weight(model.residual(&data));
+                if libm::fabs(new - *weight) > self.tolerance {
+                    converged = false;
+                }
+                *weight = new;
+            }
+            refined = Some(model);
+            if converged {
+                break;
+            }
+        }
+        refined.map(|model| (model, weights))
+    }
+}
+
+impl<E, K, Data> Refiner<E, Data> for Irls<K>
+where
+    E: WeightedEstimator<Data>,
+    K: RobustKernel,
+{
+    fn refine<I, R, T>(
+        &mut self,
+        estimator: &E,
+        model: &E::Model,
+        data: I,
+        _threshold: f64,
+        _rng: &mut R,
+        termination: &mut T,
+    ) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+        R: RngCore,
+        T: Termination,
+    {
+        self.reweight(estimator, model, data, || termination.is_interrupted())
+            .map(|(model, _)| model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{noisy_line, rng, Line, LineEstimator, LINE};
+    use crate::{Cancellation, Cauchy, GemanMcClure, Huber, NonMinimalEstimator, Tukey};
+
+    /// Refines a rough line through `data` with `kernel` and checks that it is close to [`LINE`].
+    fn check_recovers_line<K>(kernel: K, data: &[[f64; 2]])
+    where
+        K: RobustKernel,
+    {
+        let start = Line {
+            slope: 2.2,
+            intercept: 0.5,
+        };
+        let (line, weights) = Irls::new(kernel)
+            .max_iterations(50)
+            .refine_weighted(&LineEstimator::default(), &start, data.iter().copied())
+            .unwrap();
+        assert!((line.slope - LINE.slope).abs() < 0.02);
+        assert!((line.intercept - LINE.intercept).abs() < 0.1);
+        assert_eq!(weights.len(), data.len());
+    }
+
+    #[test]
+    fn refinement_recovers_a_line_despite_outliers() {
+        let data = noisy_line(0, 100, 30);
+        // Least squares on all of the data is thrown off by the outliers.
+        let least_squares = LineEstimator::default()
+            .estimate_non_minimal(data.iter().copied())
+            .unwrap();
+        assert!((least_squares.slope - LINE.slope).abs() > 0.1);
+        check_recovers_line(Huber::new(0.2), &data);
+        check_recovers_line(Cauchy::new(0.2), &data);
+        check_recovers_line(Tukey::new(2.0), &data);
+        check_recovers_line(GemanMcClure::new(0.5), &data);
+    }
+
+    #[test]
+    fn cancellation_interrupts_the_reweighting() {
+        let data = noisy_line(0, 100, 100);
+        let estimator = LineEstimator::default();
+        let start = Line {
+            slope: 0.0,
+            intercept: 0.0,
+        };
+        let mut irls = Irls::new(Huber::new(0.2)).tolerance(0.0);
+        let mut cancellation = Cancellation::new(|| estimator.fits.get() >= 2);
+        let refined = irls.refine(
+            &estimator,
+            &start,
+            data.iter().copied(),
+            0.2,
+            &mut rng(0),
+            &mut cancellation,
+        );
+        assert!(refined.is_some());
+        assert_eq!(estimator.fits.get(), 2);
+    }
+}
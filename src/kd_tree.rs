@@ -0,0 +1,104 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// A balanced kd-tree over a set of points, used to find their nearest neighbors.
+///
+/// The tree is implicit: the node of every subrange of `order` is its middle element, which splits the
+/// subrange along `axis` at that position.
+pub(crate) struct KdTree<'a, const D: usize> {
+    points: &'a [[f64; D]],
+    order: Vec<usize>,
+    axis: Vec<usize>,
+}
+
+impl<'a, const D: usize> KdTree<'a, D> {
+    pub(crate) fn new(points: &'a [[f64; D]]) -> Self {
+        let mut order: Vec<usize> = (0..points.len()).collect();
+        let mut axis = vec![0; points.len()];
+        build(points, &mut order, &mut axis);
+        Self {
+            points,
+            order,
+            axis,
+        }
+    }
+
+    /// Finds the `k` nearest points to the point at `index`, other than itself, and stores them in `nearest`
+    /// along with their squared distance, from nearest to farthest.
+    pub(crate) fn nearest(&self, index: usize, k: usize, nearest: &mut Vec<(f64, usize)>) {
+        nearest.clear();
+        if k > 0 {
+            self.search(0, self.order.len(), index, k, nearest);
+        }
+    }
+
+    fn search(
+        &self,
+        start: usize,
+        end: usize,
+        index: usize,
+        k: usize,
+        nearest: &mut Vec<(f64, usize)>,
+    ) {
+        if start >= end {
+            return;
+        }
+        let mid = start + (end - start) / 2;
+        let node = self.order[mid];
+        let query = &self.points[index];
+        if node != index {
+            let distance = squared_distance(query, &self.points[node]);
+            if nearest.len() < k || distance < nearest[k - 1].0 {
+                let position = nearest.partition_point(|&(d, _)| d <= distance);
+                nearest.insert(position, (distance, node));
+                nearest.truncate(k);
+            }
+        }
+        // Without any dimensions all of the points coincide, so there is no splitting plane.
+        let offset = if D == 0 {
+            0.0
+        } else {
+            let axis = self.axis[mid];
+            query[axis] - self.points[node][axis]
+        };
+        let (near, far) = if offset < 0.0 {
+            ((start, mid), (mid + 1, end))
+        } else {
+            ((mid + 1, end), (start, mid))
+        };
+        self.search(near.0, near.1, index, k, nearest);
+        // The far side can only contain a nearer point if the splitting plane is nearer than the farthest one.
+        if nearest.len() < k || offset * offset < nearest[k - 1].0 {
+            self.search(far.0, far.1, index, k, nearest);
+        }
+    }
+}
+
+/// Recursively splits `order` at its middle along the axis with the largest spread.
+fn build<const D: usize>(points: &[[f64; D]], order: &mut [usize], axis: &mut [usize]) {
+    if order.len() <= 1 || D == 0 {
+        return;
+    }
+    let split = (0..D)
+        .map(|axis| {
+            let (min, max) = order
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &ix| {
+                    (min.min(points[ix][axis]), max.max(points[ix][axis]))
+                });
+            (axis, max - min)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(0, |(axis, _)| axis);
+    let mid = order.len() / 2;
+    order.select_nth_unstable_by(mid, |&a, &b| points[a][split].total_cmp(&points[b][split]));
+    axis[mid] = split;
+    let (order_left, order_right) = order.split_at_mut(mid);
+    let (axis_left, axis_right) = axis.split_at_mut(mid);
+    build(points, order_left, axis_left);
+    build(points, &mut order_right[1..], &mut axis_right[1..]);
+}
+
+pub(crate) fn squared_distance<const D: usize>(a: &[f64; D], b: &[f64; D]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
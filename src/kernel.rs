@@ -0,0 +1,331 @@
+use crate::{CountScorer, Score, Scorer};
+use alloc::vec::Vec;
+
+/// A `RobustKernel` turns residuals into robust costs, so that datapoints which fit a model poorly have less
+/// influence on it than they would with least squares.
+///
+/// Kernels can be used both for scoring, with a [`KernelScorer`], and for refinement, with iteratively reweighted
+/// least squares in [`Irls`]. With the cost `ρ(r)` of a residual `r`, the derivative is `ρ'(r)` and the weight in
+/// weighted least squares is `ρ'(r) / r`. The kernels in this crate behave like least squares for small residuals,
+/// where `ρ(r) ≈ r² / 2` and the weight is `1`, and take a scale beyond which residuals are considered large.
+///
+/// [`Irls`]: crate::Irls
+pub trait RobustKernel {
+    /// The cost `ρ(r)` of a datapoint with the given residual.
+    fn cost(&self, residual: f64) -> f64;
+
+    /// The derivative `ρ'(r)` of the cost with respect to the residual.
+    fn derivative(&self, residual: f64) -> f64;
+
+    /// The weight `ρ'(r) / r` of a datapoint with the given residual in weighted least squares, which should be
+    /// between `0` and `1` and not increase with the residual.
+    fn weight(&self, residual: f64) -> f64;
+}
+
+/// The Huber kernel, which is quadratic up to the scale and linear beyond it.
+///
+/// It is convex, so outliers are down-weighted but never ignored.
+#[derive(Copy, Clone, Debug)]
+pub struct Huber {
+    scale: f64,
+}
+
+impl Huber {
+    /// Creates a new `Huber` kernel which is linear for residuals beyond `scale`.
+    pub fn new(scale: f64) -> Self {
+        Self { scale }
+    }
+}
+
+impl RobustKernel for Huber {
+    fn cost(&self, residual: f64) -> f64 {
+        if residual <= self.scale {
+            0.5 * residual * residual
+        } else {
+            self.scale * (residual - 0.5 * self.scale)
+        }
+    }
+
+    fn derivative(&self, residual: f64) -> f64 {
+        residual.min(self.scale)
+    }
+
+    fn weight(&self, residual: f64) -> f64 {
+        if residual <= self.scale {
+            1.0
+        } else {
+            self.scale / residual
+        }
+    }
+}
+
+/// The Cauchy (or Lorentzian) kernel, whose cost grows logarithmically beyond the scale.
+#[derive(Copy, Clone, Debug)]
+pub struct Cauchy {
+    scale: f64,
+}
+
+impl Cauchy {
+    /// Creates a new `Cauchy` kernel which halves the weight of a residual of `scale`.
+    pub fn new(scale: f64) -> Self {
+        Self { scale }
+    }
+}
+
+impl RobustKernel for Cauchy {
+    fn cost(&self, residual: f64) -> f64 {
+        let u = residual / self.scale;
+        0.5 * self.scale * self.scale * libm::log1p(u * u)
+    }
+
+    fn derivative(&self, residual: f64) -> f64 {
+        residual * self.weight(residual)
+    }
+
+    fn weight(&self, residual: f64) -> f64 {
+        let u = residual / self.scale;
+        1.0 / (1.0 + u * u)
+    }
+}
+
+/// Tukey's biweight (or bisquare) kernel, whose cost is constant beyond the scale.
+///
+/// Residuals beyond the scale get a weight of `0`, so outliers are ignored entirely.
+#[derive(Copy, Clone, Debug)]
+pub struct Tukey {
+    scale: f64,
+}
+
+impl Tukey {
+    /// Creates a new `Tukey` kernel which ignores residuals beyond `scale`.
+    pub fn new(scale: f64) -> Self {
+        Self { scale }
+    }
+}
+
+impl RobustKernel for Tukey {
+    fn cost(&self, residual: f64) -> f64 {
+        let u = (residual / self.scale).min(1.0);
+        let v = 1.0 - u * u;
+        self.scale * self.scale / 6.0 * (1.0 - v * v * v)
+    }
+
+    fn derivative(&self, residual: f64) -> f64 {
+        residual * self.weight(residual)
+    }
+
+    fn weight(&self, residual: f64) -> f64 {
+        if residual < self.scale {
+            let u = residual / self.scale;
+            let v = 1.0 - u * u;
+            v * v
+        } else {
+            0.0
+        }
+    }
+}
+
+/// The Geman-McClure kernel, whose cost approaches a constant for large residuals.
+#[derive(Copy, Clone, Debug)]
+pub struct GemanMcClure {
+    scale: f64,
+}
+
+impl GemanMcClure {
+    /// Creates a new `GemanMcClure` kernel whose cost of a residual of `scale` is half of its maximum.
+    pub fn new(scale: f64) -> Self {
+        Self { scale }
+    }
+}
+
+impl RobustKernel for GemanMcClure {
+    fn cost(&self, residual: f64) -> f64 {
+        let squared_scale = self.scale * self.scale;
+        let squared_residual = residual * residual;
+        0.5 * squared_scale * squared_residual / (squared_scale + squared_residual)
+    }
+
+    fn derivative(&self, residual: f64) -> f64 {
+        residual * self.weight(residual)
+    }
+
+    fn weight(&self, residual: f64) -> f64 {
+        let squared_scale = self.scale * self.scale;
+        let denominator = squared_scale + residual * residual;
+        squared_scale * squared_scale / (denominator * denominator)
+    }
+}
+
+/// The truncated quadratic kernel, which is least squares up to the scale and constant beyond it, as in MSAC.
+#[derive(Copy, Clone, Debug)]
+pub struct TruncatedL2 {
+    scale: f64,
+}
+
+impl TruncatedL2 {
+    /// Creates a new `TruncatedL2` kernel which ignores residuals from `scale` onwards.
+    pub fn new(scale: f64) -> Self {
+        Self { scale }
+    }
+}
+
+impl RobustKernel for TruncatedL2 {
+    fn cost(&self, residual: f64) -> f64 {
+        let residual = residual.min(self.scale);
+        0.5 * residual * residual
+    }
+
+    fn derivative(&self, residual: f64) -> f64 {
+        residual * self.weight(residual)
+    }
+
+    fn weight(&self, residual: f64) -> f64 {
+        if residual < self.scale {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Scores a model with the total cost of a [`RobustKernel`] over all of the data, where inliers are the datapoints
+/// with a residual below a threshold.
+///
+/// With the [`TruncatedL2`] kernel and the threshold as its scale, this ranks models like the
+/// [`MsacScorer`](crate::MsacScorer).
+#[derive(Copy, Clone, Debug)]
+pub struct KernelScorer<K> {
+    kernel: K,
+    threshold: f64,
+}
+
+impl<K> KernelScorer<K>
+where
+    K: RobustKernel,
+{
+    /// Creates a new `KernelScorer` which sums the costs of `kernel` and considers a datapoint an inlier when its
+    /// residual is below `threshold`.
+    pub fn new(kernel: K, threshold: f64) -> Self {
+        Self { kernel, threshold }
+    }
+}
+
+impl<K> Scorer for KernelScorer<K>
+where
+    K: RobustKernel,
+{
+    fn score<I>(&mut self, residuals: I) -> Score
+    where
+        I: Iterator<Item = f64>,
+    {
+        let (cost, inliers) = residuals.fold((0.0, 0), |(cost, inliers), residual| {
+            (
+                cost + self.kernel.cost(residual),
+                inliers + (residual < self.threshold) as usize,
+            )
+        });
+        Score {
+            value: -cost,
+            inliers,
+        }
+    }
+
+    fn inliers<I>(&mut self, residuals: I) -> Vec<usize>
+    where
+        I: Iterator<Item = f64>,
+    {
+        CountScorer::new(self.threshold).inliers(residuals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MsacScorer;
+
+    /// Checks the properties every kernel in this crate shares at the given scale.
+    fn check_kernel<K>(kernel: K, scale: f64)
+    where
+        K: RobustKernel,
+    {
+        assert_eq!(kernel.cost(0.0), 0.0);
+        assert_eq!(kernel.derivative(0.0), 0.0);
+        assert_eq!(kernel.weight(0.0), 1.0);
+        let mut previous = 1.0;
+        for step in 1..400 {
+            let residual = scale * step as f64 / 100.0;
+            let weight = kernel.weight(residual);
+            assert!((0.0..=previous).contains(&weight));
+            assert!((kernel.derivative(residual) - residual * weight).abs() < 1e-12);
+            // Near zero, the kernels behave like least squares.
+            if residual <= 0.01 * scale {
+                assert!(
+                    (kernel.cost(residual) - 0.5 * residual * residual).abs()
+                        < 1e-3 * residual * residual
+                );
+            }
+            previous = weight;
+        }
+    }
+
+    #[test]
+    fn huber_is_linear_beyond_the_scale() {
+        let huber = Huber::new(2.0);
+        check_kernel(huber, 2.0);
+        assert_eq!(huber.weight(1.0), 1.0);
+        assert_eq!(huber.weight(2.0), 1.0);
+        assert_eq!(huber.weight(4.0), 0.5);
+        assert_eq!(huber.cost(2.0), 2.0);
+        assert_eq!(huber.cost(4.0), 6.0);
+        assert_eq!(huber.derivative(4.0), 2.0);
+    }
+
+    #[test]
+    fn cauchy_halves_the_weight_at_the_scale() {
+        let cauchy = Cauchy::new(2.0);
+        check_kernel(cauchy, 2.0);
+        assert_eq!(cauchy.weight(2.0), 0.5);
+        assert!((cauchy.cost(2.0) - 2.0 * core::f64::consts::LN_2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn tukey_ignores_residuals_beyond_the_scale() {
+        let tukey = Tukey::new(2.0);
+        check_kernel(tukey, 2.0);
+        assert_eq!(tukey.weight(1.0), 0.5625);
+        assert!(tukey.weight(1.999) > 0.0);
+        assert_eq!(tukey.weight(2.0), 0.0);
+        assert_eq!(tukey.weight(4.0), 0.0);
+        assert_eq!(tukey.cost(2.0), 4.0 / 6.0);
+        assert_eq!(tukey.cost(4.0), 4.0 / 6.0);
+    }
+
+    #[test]
+    fn geman_mcclure_reaches_half_of_its_maximum_cost_at_the_scale() {
+        let geman_mcclure = GemanMcClure::new(2.0);
+        check_kernel(geman_mcclure, 2.0);
+        assert_eq!(geman_mcclure.weight(2.0), 0.25);
+        assert_eq!(geman_mcclure.cost(2.0), 1.0);
+        assert!(geman_mcclure.cost(1e6) < 2.0);
+    }
+
+    #[test]
+    fn truncated_l2_ignores_residuals_from_the_scale() {
+        let truncated = TruncatedL2::new(2.0);
+        check_kernel(truncated, 2.0);
+        assert_eq!(truncated.weight(1.999), 1.0);
+        assert_eq!(truncated.weight(2.0), 0.0);
+        assert_eq!(truncated.cost(1.0), 0.5);
+        assert_eq!(truncated.cost(2.0), 2.0);
+        assert_eq!(truncated.cost(4.0), 2.0);
+    }
+
+    #[test]
+    fn truncated_l2_scores_like_msac() {
+        let residuals = [0.0, 0.5, 1.0, 1.5, 2.0, 10.0];
+        let score = KernelScorer::new(TruncatedL2::new(1.2), 1.2).score(residuals.iter().copied());
+        let msac = MsacScorer::new(1.2).score(residuals.iter().copied());
+        assert!((score.value - 0.5 * msac.value).abs() < 1e-12);
+        assert_eq!(score.inliers, msac.inliers);
+    }
+}
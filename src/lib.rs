@@ -1,5 +1,60 @@
 #![no_std]
 
+extern crate alloc;
+
+mod arrsac;
+mod degensac;
+#[cfg(test)]
+mod fixtures;
+mod gamma;
+mod gc_ransac;
+mod irls;
+mod kd_tree;
+mod kernel;
+mod lo_ransac;
+mod magsac;
+mod maxflow;
+mod mlesac;
+mod msac;
+mod neighborhood;
+mod preemptive;
+mod prosac;
+mod ransac;
+mod refine;
+mod report;
+mod sample;
+mod sampler;
+mod scorer;
+mod search;
+mod sprt;
+mod termination;
+mod usac;
+
+pub use arrsac::Arrsac;
+pub use degensac::{Degeneracy, Degensac};
+pub use gc_ransac::{GcRansac, GraphCut};
+pub use irls::Irls;
+pub use kernel::{Cauchy, GemanMcClure, Huber, KernelScorer, RobustKernel, TruncatedL2, Tukey};
+pub use lo_ransac::LoRansac;
+pub use magsac::{MagsacPlusPlus, MagsacScorer};
+pub use mlesac::{Mlesac, MlesacScorer};
+pub use msac::{Msac, MsacScorer};
+pub use neighborhood::{Neighborhood, Neighbors};
+pub use preemptive::PreemptiveRansac;
+pub use prosac::{Prosac, ProsacSampler};
+pub use ransac::Ransac;
+pub use refine::{InnerRansac, Refiner, Refit};
+pub use report::{ConsensusError, ConsensusReport, ReportingConsensus, TerminationReason};
+pub use sampler::{Guided, Napsac, ProgressiveNapsac, Sampler, Uniform, MAX_SAMPLE_SIZE};
+pub use scorer::{CountScorer, Score, Scorer};
+pub use sprt::{Sprt, Verification};
+pub use termination::{
+    achieved_confidence, prosac_required_iterations, required_iterations, sprt_required_iterations,
+    Adaptive, All, Any, BestHypothesis, Cancel, Cancellation, Clock, Confidence, MaxIterations,
+    Plateau, Termination, TimeBudget,
+};
+pub use usac::{AcceptAll, ModelCheck, SampleCheck, Usac, Verifier};
+
 /// A model is a best-fit of at least some of the underlying data. You can compute residuals in respect to the model.
 pub trait Model<Data> {
     /// Note that the residual error is returned as a 64-bit float. This allows the residual to be used for things
@@ -20,6 +75,8 @@ pub trait Estimator<Data> {
     type ModelIter: IntoIterator<Item = Self::Model>;
 
     /// The minimum number of samples that the estimator can estimate a model from.
+    ///
+    /// The consensus implementations in this crate support up to [`MAX_SAMPLE_SIZE`].
     const MIN_SAMPLES: usize;
 
     /// Takes in an iterator over the data and produces a model that best fits the data.
@@ -33,6 +90,65 @@ pub trait Estimator<Data> {
     fn estimate<I>(&self, data: I) -> Self::ModelIter
     where
         I: Iterator<Item = Data> + Clone;
+
+    /// Checks a minimal sample of `Self::MIN_SAMPLES` data points before a model is estimated from it.
+    ///
+    /// Returning `false` rejects the sample without calling [`Estimator::estimate`], which is useful for
+    /// degenerate samples that solvers fail on or produce garbage from, such as collinear points for a homography
+    /// or coincident points for a line. The consensus implementations in this crate count the rejected samples.
+    ///
+    /// By default every sample is accepted.
+    fn is_sample_valid<I>(&self, _sample: I) -> bool
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        true
+    }
+
+    /// Checks a model right after it is estimated from the minimal `sample`, before it is verified on the data.
+    ///
+    /// Returning `false` discards the model without computing any residuals, which is useful for models that
+    /// are implausible given only the sample, such as an essential matrix that puts points behind a camera or
+    /// has an extreme scale.
+    ///
+    /// By default every model is accepted.
+    fn is_model_valid<I>(&self, _model: &Self::Model, _sample: I) -> bool
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        true
+    }
+}
+
+/// A `NonMinimalEstimator` is an [`Estimator`] which can also fit a model to any number of data points,
+/// such as with least squares. This is used to refine a model using all of the data that supports it, both
+/// after consensus and during local optimization.
+///
+/// A [`WeightedEstimator`] without a dedicated unweighted fit can implement this by giving every data point
+/// a weight of `1`.
+pub trait NonMinimalEstimator<Data>: Estimator<Data> {
+    /// Takes in an iterator over the data and produces the model that best fits all of it.
+    ///
+    /// Any number of data points may be passed. `None` should be returned if a model is impossible to estimate
+    /// based on the data, which includes when there are too few data points.
+    fn estimate_non_minimal<I>(&self, data: I) -> Option<Self::Model>
+    where
+        I: Iterator<Item = Data> + Clone;
+}
+
+/// A `WeightedEstimator` is an [`Estimator`] which can also fit a model to any number of weighted data points,
+/// such as with weighted least squares. This is used to refine a model using all of the data that supports it.
+///
+/// This is the weighted version of [`NonMinimalEstimator`].
+pub trait WeightedEstimator<Data>: Estimator<Data> {
+    /// Takes in an iterator over the data, each paired with a positive weight, and produces the model that
+    /// best fits the data, where each data point contributes in proportion to its weight.
+    ///
+    /// Any number of data points may be passed. `None` should be returned if a model is impossible to estimate
+    /// based on the data, which includes when there are too few data points.
+    fn estimate_weighted<I>(&self, data: I) -> Option<Self::Model>
+    where
+        I: Iterator<Item = (Data, f64)> + Clone;
 }
 
 /// A consensus algorithm extracts a consensus from an underlying model of data.
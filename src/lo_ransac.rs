@@ -0,0 +1,287 @@
+use crate::search::Search;
+use crate::{
+    AcceptAll, Adaptive, Consensus, ConsensusReport, CountScorer, Estimator, InnerRansac, Model,
+    Refiner, ReportingConsensus, Sampler, Scorer, Sprt, Termination, Uniform,
+};
+use alloc::vec::Vec;
+use rand_core::RngCore;
+
+/// The LO-RANSAC (locally optimized RANSAC) algorithm.
+///
+/// This samples, scores, and terminates exactly like [`Ransac`](crate::Ransac), but whenever a sample produces a
+/// new best hypothesis, the hypothesis is handed to a [`Refiner`] which attempts to improve it using its inliers.
+/// The refined model replaces the hypothesis if it scores higher. Since the refined model usually has more
+/// inliers than any minimal sample could produce, this also makes the adaptive termination kick in sooner.
+///
+/// By default, the refiner is [`InnerRansac`], which is the local optimization from the LO-RANSAC paper. The
+/// hypotheses can be scored by any [`Scorer`], while the refiner keeps using the inlier threshold.
+#[derive(Clone, Debug)]
+pub struct LoRansac<R, F = InnerRansac, S = Uniform, C = CountScorer, T = Adaptive> {
+    threshold: f64,
+    scorer: C,
+    termination: T,
+    sprt: Option<Sprt>,
+    rng: R,
+    sampler: S,
+    rejected_samples: usize,
+    refiner: F,
+}
+
+impl<R> LoRansac<R>
+where
+    R: RngCore,
+{
+    /// Creates a new `LoRansac` which considers a datapoint an inlier when its residual is below `threshold`.
+    ///
+    /// `rng` is used to draw the samples. By default, at most `1000` iterations are run and iteration stops
+    /// early once an all-inlier sample has been drawn with a confidence of `0.99`.
+    pub fn new(threshold: f64, rng: R) -> Self {
+        Self {
+            threshold,
+            scorer: CountScorer::new(threshold),
+            termination: Adaptive::new(0.99, 1000),
+            sprt: None,
+            rng,
+            sampler: Uniform,
+            rejected_samples: 0,
+            refiner: InnerRansac::new(),
+        }
+    }
+}
+
+impl<R, F, S, C> LoRansac<R, F, S, C>
+where
+    R: RngCore,
+{
+    /// The maximum number of samples to draw.
+    ///
+    /// Default: `1000`
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            termination: self.termination.max_iterations(max_iterations),
+            ..self
+        }
+    }
+
+    /// The probability of having drawn at least one all-inlier sample at which iteration stops.
+    ///
+    /// Default: `0.99`
+    pub fn confidence(self, confidence: f64) -> Self {
+        Self {
+            termination: self.termination.confidence(confidence),
+            ..self
+        }
+    }
+}
+
+impl<R, F, S, T> LoRansac<R, F, S, CountScorer, T>
+where
+    R: RngCore,
+{
+    /// The residual below which a datapoint is considered an inlier.
+    ///
+    /// Default: specified in [`LoRansac::new`]
+    pub fn threshold(self, threshold: f64) -> Self {
+        Self {
+            threshold,
+            scorer: CountScorer::new(threshold),
+            ..self
+        }
+    }
+}
+
+impl<R, F, S, C, T> LoRansac<R, F, S, C, T>
+where
+    R: RngCore,
+{
+    /// Verifies hypotheses with the [`Sprt`] so that bad ones are rejected early.
+    ///
+    /// Default: every hypothesis is evaluated on all of the data
+    pub fn sprt(self, sprt: Sprt) -> Self {
+        Self {
+            sprt: Some(sprt),
+            ..self
+        }
+    }
+
+    /// The [`Refiner`] used to locally optimize new best hypotheses.
+    ///
+    /// Default: [`InnerRansac::new`]
+    pub fn refiner<G>(self, refiner: G) -> LoRansac<R, G, S, C, T> {
+        LoRansac {
+            threshold: self.threshold,
+            scorer: self.scorer,
+            termination: self.termination,
+            sprt: self.sprt,
+            rng: self.rng,
+            sampler: self.sampler,
+            rejected_samples: self.rejected_samples,
+            refiner,
+        }
+    }
+
+    /// The [`Sampler`] which draws the minimal samples.
+    ///
+    /// Default: [`Uniform`]
+    pub fn sampler<X>(self, sampler: X) -> LoRansac<R, F, X, C, T> {
+        LoRansac {
+            threshold: self.threshold,
+            scorer: self.scorer,
+            termination: self.termination,
+            sprt: self.sprt,
+            rng: self.rng,
+            sampler,
+            rejected_samples: self.rejected_samples,
+            refiner: self.refiner,
+        }
+    }
+
+    /// The [`Termination`] criterion which decides when to stop drawing samples, instead of the maximum number
+    /// of iterations and the confidence.
+    ///
+    /// Default: [`Adaptive`] with a confidence of `0.99` and at most `1000` iterations
+    pub fn termination<X>(self, termination: X) -> LoRansac<R, F, S, C, X> {
+        LoRansac {
+            threshold: self.threshold,
+            scorer: self.scorer,
+            termination,
+            sprt: self.sprt,
+            rng: self.rng,
+            sampler: self.sampler,
+            rejected_samples: self.rejected_samples,
+            refiner: self.refiner,
+        }
+    }
+
+    /// The [`Scorer`] which scores the hypotheses, while local optimization keeps using the inlier
+    /// threshold.
+    ///
+    /// Default: [`CountScorer`] with the threshold specified in [`LoRansac::new`]
+    pub fn scorer<D>(self, scorer: D) -> LoRansac<R, F, S, D, T> {
+        LoRansac {
+            threshold: self.threshold,
+            scorer,
+            termination: self.termination,
+            sprt: self.sprt,
+            rng: self.rng,
+            sampler: self.sampler,
+            rejected_samples: self.rejected_samples,
+            refiner: self.refiner,
+        }
+    }
+
+    /// The number of minimal samples rejected by [`Estimator::is_sample_valid`] during the last search.
+    pub fn rejected_samples(&self) -> usize {
+        self.rejected_samples
+    }
+}
+
+impl<R, F, S, C, T> LoRansac<R, F, S, C, T>
+where
+    R: RngCore,
+    S: Sampler,
+    C: Scorer,
+    T: Termination,
+{
+    /// Runs the search and reports the best model, without its inliers.
+    fn search<E, Data, I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        E: Estimator<Data>,
+        F: Refiner<E, Data>,
+        I: Iterator<Item = Data> + Clone,
+    {
+        let threshold = self.threshold;
+        let scorer = &mut self.scorer;
+        let refiner = &mut self.refiner;
+        Search {
+            rng: &mut self.rng,
+            sampler: &mut self.sampler,
+            sample_check: &mut AcceptAll,
+            model_check: &mut AcceptAll,
+            verifier: &mut self.sprt,
+            rejected_samples: &mut self.rejected_samples,
+            termination: &mut self.termination,
+        }
+        .run_optimized(
+            estimator,
+            data,
+            |model, data| {
+                let score = scorer.score(data.map(|data| model.residual(&data)));
+                (score.value, score.inliers)
+            },
+            |rng, termination, model, _, data| {
+                refiner.refine(estimator, model, data, threshold, rng, termination)
+            },
+        )
+    }
+}
+
+impl<E, R, F, S, C, T, Data> Consensus<E, Data> for LoRansac<R, F, S, C, T>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    F: Refiner<E, Data>,
+    S: Sampler,
+    C: Scorer,
+    T: Termination,
+{
+    type Inliers = Vec<usize>;
+
+    fn model<I>(&mut self, estimator: &E, data: I) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.search(estimator, data).model
+    }
+
+    fn model_inliers<I>(&mut self, estimator: &E, data: I) -> Option<(E::Model, Self::Inliers)>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let report = self.model_report(estimator, data);
+        Some((report.model?, report.inliers))
+    }
+}
+
+impl<E, R, F, S, C, T, Data> ReportingConsensus<E, Data> for LoRansac<R, F, S, C, T>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    F: Refiner<E, Data>,
+    S: Sampler,
+    C: Scorer,
+    T: Termination,
+{
+    fn model_report<I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let mut report = self.search(estimator, data.clone());
+        if let Some(model) = &report.model {
+            report.inliers = self.scorer.inliers(data.map(|data| model.residual(&data)));
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{noisy_line, rng, LineEstimator};
+    use crate::{Cancellation, TerminationReason};
+
+    #[test]
+    fn cancellation_interrupts_the_local_optimization() {
+        let data = noisy_line(0, 100, 100);
+        let estimator = LineEstimator::default();
+        // Cancel as soon as the local optimization of the first hypothesis has fit a model.
+        let mut consensus = LoRansac::new(0.2, rng(0)).termination(
+            Adaptive::new(0.99, 1000).or(Cancellation::new(|| estimator.fits.get() > 0)),
+        );
+        let report = consensus.model_report(&estimator, data.iter().copied());
+        assert_eq!(estimator.fits.get(), 1);
+        assert_eq!(report.iterations, 1);
+        assert_eq!(report.termination, TerminationReason::Cancelled);
+        assert!(report.model.is_some());
+    }
+}
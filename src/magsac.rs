@@ -0,0 +1,521 @@
+use crate::gamma;
+use crate::search::Search;
+use crate::{
+    AcceptAll, Adaptive, Consensus, ConsensusReport, CountScorer, Model, ReportingConsensus,
+    Sampler, Score, Scorer, Sprt, Termination, Uniform, WeightedEstimator,
+};
+use alloc::vec::Vec;
+use rand_core::RngCore;
+
+/// The number of entries in the lookup tables of the incomplete gamma functions.
+const TABLE_SIZE: usize = 1024;
+
+/// MAGSAC++ from the paper "MAGSAC++, a fast, reliable and accurate robust estimator" by Barath et al.
+///
+/// MAGSAC++ doesn't need an inlier threshold. Instead, it marginalizes over the noise scale `sigma` up to an upper
+/// bound `sigma_max`, which is much less sensitive to tune. Every datapoint is weighted by how likely it is to be
+/// an inlier at any noise scale, and hypotheses are scored with the corresponding robust loss. Whenever a new best
+/// hypothesis is found, it is refined with iteratively reweighted least squares (σ-consensus++), so the estimator
+/// must be a [`WeightedEstimator`].
+///
+/// The residuals are assumed to follow a chi distribution with the configured degrees of freedom, which is the
+/// dimension of the residual. For instance, the residual of a homography is the distance between two 2D points,
+/// which has two degrees of freedom per point, for a total of four.
+///
+/// [`Consensus::model_inliers`] returns the datapoints with a non-zero weight. Use
+/// [`MagsacPlusPlus::model_weighted_inliers`] to get their weights as well.
+///
+/// The scoring is available on its own as [`MagsacScorer`].
+#[derive(Clone, Debug)]
+pub struct MagsacPlusPlus<R, S = Uniform, T = Adaptive> {
+    sigma_max: f64,
+    degrees_of_freedom: usize,
+    irls_iterations: usize,
+    termination: T,
+    sprt: Option<Sprt>,
+    rng: R,
+    sampler: S,
+    rejected_samples: usize,
+    /// Rebuilt only when `sigma_max` or `degrees_of_freedom` change, since its lookup tables are expensive.
+    scorer: MagsacScorer,
+    weights: Vec<f64>,
+}
+
+impl<R> MagsacPlusPlus<R>
+where
+    R: RngCore,
+{
+    /// Creates a new `MagsacPlusPlus` for residuals with a noise scale of at most `sigma_max`.
+    ///
+    /// `rng` is used to draw the samples. By default, residuals have four degrees of freedom, at most `1000`
+    /// iterations are run and iteration stops early once an all-inlier sample has been drawn with a confidence
+    /// of `0.99`.
+    pub fn new(sigma_max: f64, rng: R) -> Self {
+        Self {
+            sigma_max,
+            degrees_of_freedom: 4,
+            irls_iterations: 10,
+            termination: Adaptive::new(0.99, 1000),
+            sprt: None,
+            rng,
+            sampler: Uniform,
+            rejected_samples: 0,
+            scorer: MagsacScorer::new(sigma_max, 4),
+            weights: Vec::new(),
+        }
+    }
+}
+
+impl<R, S> MagsacPlusPlus<R, S>
+where
+    R: RngCore,
+{
+    /// The maximum number of samples to draw.
+    ///
+    /// Default: `1000`
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            termination: self.termination.max_iterations(max_iterations),
+            ..self
+        }
+    }
+
+    /// The probability of having drawn at least one all-inlier sample at which iteration stops.
+    ///
+    /// Default: `0.99`
+    pub fn confidence(self, confidence: f64) -> Self {
+        Self {
+            termination: self.termination.confidence(confidence),
+            ..self
+        }
+    }
+}
+
+impl<R, S, T> MagsacPlusPlus<R, S, T>
+where
+    R: RngCore,
+{
+    /// The upper bound on the noise scale of the residuals of inliers.
+    ///
+    /// Default: specified in [`MagsacPlusPlus::new`]
+    pub fn sigma_max(self, sigma_max: f64) -> Self {
+        Self {
+            sigma_max,
+            scorer: MagsacScorer::new(sigma_max, self.degrees_of_freedom),
+            ..self
+        }
+    }
+
+    /// The degrees of freedom of the residuals, which must be at least `2`.
+    ///
+    /// Default: `4`
+    pub fn degrees_of_freedom(self, degrees_of_freedom: usize) -> Self {
+        Self {
+            degrees_of_freedom,
+            scorer: MagsacScorer::new(self.sigma_max, degrees_of_freedom),
+            ..self
+        }
+    }
+
+    /// The maximum number of iterations of iteratively reweighted least squares used to refine
+    /// a new best hypothesis.
+    ///
+    /// Default: `10`
+    pub fn irls_iterations(self, irls_iterations: usize) -> Self {
+        Self {
+            irls_iterations,
+            ..self
+        }
+    }
+
+    /// Verifies hypotheses with the [`Sprt`] so that bad ones are rejected early.
+    ///
+    /// Default: every hypothesis is evaluated on all of the data
+    pub fn sprt(self, sprt: Sprt) -> Self {
+        Self {
+            sprt: Some(sprt),
+            ..self
+        }
+    }
+
+    /// Finds a model and the indices of the datapoints with a non-zero weight, along with their weight.
+    ///
+    /// The weights are in the range `(0, 1]`, where a weight of `1` means the residual is `0`.
+    #[allow(clippy::type_complexity)]
+    pub fn model_weighted_inliers<E, Data, I>(
+        &mut self,
+        estimator: &E,
+        data: I,
+    ) -> Option<(E::Model, Vec<(usize, f64)>)>
+    where
+        E: WeightedEstimator<Data>,
+        I: Iterator<Item = Data> + Clone,
+        S: Sampler,
+        T: Termination,
+    {
+        let model = self.model(estimator, data.clone())?;
+        let scorer = &self.scorer;
+        let max_weight = scorer.weight(0.0);
+        let inliers = data
+            .enumerate()
+            .map(|(ix, data)| (ix, scorer.weight(model.residual(&data)) / max_weight))
+            .filter(|&(_, weight)| weight > 0.0)
+            .collect();
+        Some((model, inliers))
+    }
+
+    /// The [`Sampler`] which draws the minimal samples.
+    ///
+    /// Default: [`Uniform`]
+    pub fn sampler<X>(self, sampler: X) -> MagsacPlusPlus<R, X, T> {
+        MagsacPlusPlus {
+            sigma_max: self.sigma_max,
+            degrees_of_freedom: self.degrees_of_freedom,
+            irls_iterations: self.irls_iterations,
+            termination: self.termination,
+            sprt: self.sprt,
+            rng: self.rng,
+            sampler,
+            rejected_samples: self.rejected_samples,
+            scorer: self.scorer,
+            weights: self.weights,
+        }
+    }
+
+    /// The [`Termination`] criterion which decides when to stop drawing samples, instead of the maximum number
+    /// of iterations and the confidence.
+    ///
+    /// Default: [`Adaptive`] with a confidence of `0.99` and at most `1000` iterations
+    pub fn termination<X>(self, termination: X) -> MagsacPlusPlus<R, S, X> {
+        MagsacPlusPlus {
+            sigma_max: self.sigma_max,
+            degrees_of_freedom: self.degrees_of_freedom,
+            irls_iterations: self.irls_iterations,
+            termination,
+            sprt: self.sprt,
+            rng: self.rng,
+            sampler: self.sampler,
+            rejected_samples: self.rejected_samples,
+            scorer: self.scorer,
+            weights: self.weights,
+        }
+    }
+
+    /// The number of minimal samples rejected by [`Estimator::is_sample_valid`] during the last search.
+    ///
+    /// [`Estimator::is_sample_valid`]: crate::Estimator::is_sample_valid
+    pub fn rejected_samples(&self) -> usize {
+        self.rejected_samples
+    }
+}
+
+/// Scores a model with the loss of [`MagsacPlusPlus`], which marginalizes over the noise scale up to
+/// `sigma_max`. Inliers are the datapoints with a non-zero weight, whose residuals are within the `0.99`
+/// quantile of the residuals of inliers at `sigma_max`.
+///
+/// Constant factors are dropped since they don't affect which model is better or the result of
+/// weighted least squares.
+#[derive(Clone, Debug)]
+pub struct MagsacScorer {
+    sigma_max: f64,
+    /// Residuals beyond this are outliers at any noise scale.
+    threshold: f64,
+    /// The squared residual at which the lookup tables are indexed by a step of `1`.
+    table_scale: f64,
+    /// `Γ((ν - 1) / 2, x) - Γ((ν - 1) / 2, k² / 2)` with `x = r² / (2 σ_max²)` spanning `[0, k² / 2]`.
+    upper: Vec<f64>,
+    /// `γ((ν + 1) / 2, x)` with `x = r² / (2 σ_max²)` spanning `[0, k² / 2]`.
+    lower: Vec<f64>,
+}
+
+impl MagsacScorer {
+    /// Creates a new `MagsacScorer` for residuals with a noise scale of at most `sigma_max` and the given
+    /// degrees of freedom, which must be at least `2`. See [`MagsacPlusPlus::degrees_of_freedom`].
+    pub fn new(sigma_max: f64, degrees_of_freedom: usize) -> Self {
+        assert!(
+            degrees_of_freedom >= 2,
+            "MAGSAC++ needs at least two degrees of freedom"
+        );
+        let dof = degrees_of_freedom as f64;
+        let k = gamma::chi_quantile(dof, 0.99);
+        let x_max = 0.5 * k * k;
+        let upper_k = gamma::upper(0.5 * (dof - 1.0), x_max);
+        let x = |ix: usize| x_max * ix as f64 / (TABLE_SIZE - 1) as f64;
+        Self {
+            sigma_max,
+            threshold: k * sigma_max,
+            table_scale: (TABLE_SIZE - 1) as f64 / (k * k * sigma_max * sigma_max),
+            upper: (0..TABLE_SIZE)
+                .map(|ix| gamma::upper(0.5 * (dof - 1.0), x(ix)) - upper_k)
+                .collect(),
+            lower: (0..TABLE_SIZE)
+                .map(|ix| gamma::lower(0.5 * (dof + 1.0), x(ix)))
+                .collect(),
+        }
+    }
+
+    /// Linearly interpolates a lookup table at a squared residual within the threshold.
+    fn lookup(&self, table: &[f64], squared_residual: f64) -> f64 {
+        let position = squared_residual * self.table_scale;
+        let ix = (position as usize).min(TABLE_SIZE - 2);
+        let t = position - ix as f64;
+        table[ix] + t * (table[ix + 1] - table[ix])
+    }
+
+    /// The weight of a residual in weighted least squares.
+    fn weight(&self, residual: f64) -> f64 {
+        if residual < self.threshold {
+            self.lookup(&self.upper, residual * residual)
+        } else {
+            0.0
+        }
+    }
+
+    /// The loss of a residual, which is constant beyond the threshold.
+    fn loss(&self, residual: f64) -> f64 {
+        let residual = residual.min(self.threshold);
+        let squared_residual = residual * residual;
+        0.5 * self.sigma_max * self.sigma_max * self.lookup(&self.lower, squared_residual)
+            + 0.25 * squared_residual * self.lookup(&self.upper, squared_residual)
+    }
+
+    /// Returns the total loss of the residuals and the number of them within the threshold.
+    fn evaluate<I>(&self, residuals: I) -> (f64, usize)
+    where
+        I: Iterator<Item = f64>,
+    {
+        residuals.fold((0.0, 0), |(loss, inliers), residual| {
+            (
+                loss + self.loss(residual),
+                inliers + (residual < self.threshold) as usize,
+            )
+        })
+    }
+
+    /// Refines a model with iteratively reweighted least squares, stopping once the loss doesn't improve.
+    fn refine<E, Data, I, T>(
+        &self,
+        estimator: &E,
+        model: &E::Model,
+        data: I,
+        iterations: usize,
+        weights: &mut Vec<f64>,
+        termination: &mut T,
+    ) -> Option<E::Model>
+    where
+        E: WeightedEstimator<Data>,
+        I: Iterator<Item = Data> + Clone,
+        T: Termination,
+    {
+        let mut best: Option<(E::Model, f64)> = None;
+        for _ in 0..iterations {
+            if termination.is_interrupted() {
+                break;
+            }
+            let current = best.as_ref().map_or(model, |(model, _)| model);
+            weights.clear();
+            weights.extend(
+                data.clone()
+                    .map(|data| self.weight(current.residual(&data))),
+            );
+            let weighted = data
+                .clone()
+                .zip(weights.iter().copied())
+                .filter(|&(_, weight)| weight > 0.0);
+            let Some(refined) = estimator.estimate_weighted(weighted) else {
+                break;
+            };
+            let (loss, _) = self.evaluate(data.clone().map(|data| refined.residual(&data)));
+            if best.as_ref().is_some_and(|&(_, best)| loss >= best) {
+                break;
+            }
+            best = Some((refined, loss));
+        }
+        best.map(|(model, _)| model)
+    }
+}
+
+impl Scorer for MagsacScorer {
+    fn score<I>(&mut self, residuals: I) -> Score
+    where
+        I: Iterator<Item = f64>,
+    {
+        let (loss, inliers) = self.evaluate(residuals);
+        Score {
+            value: -loss,
+            inliers,
+        }
+    }
+
+    fn inliers<I>(&mut self, residuals: I) -> Vec<usize>
+    where
+        I: Iterator<Item = f64>,
+    {
+        CountScorer::new(self.threshold).inliers(residuals)
+    }
+}
+
+impl<R, S, T> MagsacPlusPlus<R, S, T>
+where
+    R: RngCore,
+    S: Sampler,
+    T: Termination,
+{
+    /// Runs the search and reports the best model, without its inliers.
+    fn search<E, Data, I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        E: WeightedEstimator<Data>,
+        I: Iterator<Item = Data> + Clone,
+    {
+        let scorer = &self.scorer;
+        let irls_iterations = self.irls_iterations;
+        let weights = &mut self.weights;
+        Search {
+            rng: &mut self.rng,
+            sampler: &mut self.sampler,
+            sample_check: &mut AcceptAll,
+            model_check: &mut AcceptAll,
+            verifier: &mut self.sprt,
+            rejected_samples: &mut self.rejected_samples,
+            termination: &mut self.termination,
+        }
+        .run_optimized(
+            estimator,
+            data,
+            |model, data| {
+                let (loss, inliers) = scorer.evaluate(data.map(|data| model.residual(&data)));
+                (-loss, inliers)
+            },
+            |_, termination, model, _, data| {
+                scorer.refine(
+                    estimator,
+                    model,
+                    data,
+                    irls_iterations,
+                    weights,
+                    termination,
+                )
+            },
+        )
+    }
+}
+
+impl<E, R, S, T, Data> Consensus<E, Data> for MagsacPlusPlus<R, S, T>
+where
+    E: WeightedEstimator<Data>,
+    R: RngCore,
+    S: Sampler,
+    T: Termination,
+{
+    type Inliers = Vec<usize>;
+
+    fn model<I>(&mut self, estimator: &E, data: I) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.search(estimator, data).model
+    }
+
+    fn model_inliers<I>(&mut self, estimator: &E, data: I) -> Option<(E::Model, Self::Inliers)>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let (model, inliers) = self.model_weighted_inliers(estimator, data)?;
+        Some((model, inliers.into_iter().map(|(ix, _)| ix).collect()))
+    }
+}
+
+impl<E, R, S, T, Data> ReportingConsensus<E, Data> for MagsacPlusPlus<R, S, T>
+where
+    E: WeightedEstimator<Data>,
+    R: RngCore,
+    S: Sampler,
+    T: Termination,
+{
+    fn model_report<I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let mut report = self.search(estimator, data.clone());
+        if let Some(model) = &report.model {
+            let scorer = &self.scorer;
+            report.inliers = data
+                .enumerate()
+                .filter(|(_, data)| scorer.weight(model.residual(data)) > 0.0)
+                .map(|(ix, _)| ix)
+                .collect();
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{noisy_line, rng, LineEstimator, LINE};
+
+    #[test]
+    fn weights_follow_the_marginalized_formula() {
+        let scorer = MagsacScorer::new(1.0, 4);
+        let k = gamma::chi_quantile(4.0, 0.99);
+        assert!(libm::fabs(scorer.threshold - k) < 1e-12);
+        // w(r) = Γ((ν - 1) / 2, r² / 2σ²) - Γ((ν - 1) / 2, k² / 2), up to the interpolation of the table.
+        for residual in [0.0, 0.5, 1.0, 2.0, 3.0, 3.6] {
+            let expected =
+                gamma::upper(1.5, 0.5 * residual * residual) - gamma::upper(1.5, 0.5 * k * k);
+            assert!(libm::fabs(scorer.weight(residual) - expected) < 1e-4);
+        }
+        assert_eq!(scorer.weight(k), 0.0);
+        assert_eq!(scorer.weight(10.0), 0.0);
+    }
+
+    #[test]
+    fn weights_decrease_and_losses_increase_with_the_residual() {
+        let scorer = MagsacScorer::new(2.0, 2);
+        let residuals = (0..=100).map(|ix| ix as f64 * scorer.threshold / 100.0);
+        let weights: Vec<f64> = residuals
+            .clone()
+            .map(|residual| scorer.weight(residual))
+            .collect();
+        let losses: Vec<f64> = residuals.map(|residual| scorer.loss(residual)).collect();
+        assert!(weights.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert!(losses.windows(2).all(|pair| pair[1] >= pair[0]));
+        assert!(weights[0] > 0.0);
+        assert_eq!(weights[100], 0.0);
+        assert_eq!(scorer.loss(scorer.threshold), scorer.loss(100.0));
+    }
+
+    #[test]
+    fn weights_scale_with_sigma_max() {
+        let (narrow, wide) = (MagsacScorer::new(1.0, 4), MagsacScorer::new(3.0, 4));
+        for residual in [0.0, 0.3, 1.0, 2.5] {
+            assert!(libm::fabs(narrow.weight(residual) - wide.weight(3.0 * residual)) < 1e-12);
+        }
+    }
+
+    #[test]
+    fn builders_rebuild_the_scorer() {
+        let magsac = MagsacPlusPlus::new(1.0, rng(0))
+            .sigma_max(2.0)
+            .degrees_of_freedom(2);
+        let expected = MagsacScorer::new(2.0, 2);
+        assert_eq!(magsac.scorer.threshold, expected.threshold);
+        assert_eq!(magsac.scorer.upper, expected.upper);
+        assert_eq!(magsac.scorer.lower, expected.lower);
+    }
+
+    #[test]
+    fn sigma_consensus_recovers_a_line() {
+        let data = noisy_line(0, 100, 100);
+        let mut magsac = MagsacPlusPlus::new(0.1, rng(0)).degrees_of_freedom(2);
+        let (line, inliers) = magsac
+            .model_weighted_inliers(&LineEstimator::default(), data.iter().copied())
+            .unwrap();
+        assert!(libm::fabs(line.slope - LINE.slope) < 0.02);
+        assert!(libm::fabs(line.intercept - LINE.intercept) < 0.1);
+        assert!(inliers.len() >= 100);
+        assert!(inliers
+            .iter()
+            .all(|&(_, weight)| weight > 0.0 && weight <= 1.0));
+    }
+}
@@ -0,0 +1,207 @@
+use alloc::vec::Vec;
+
+const NONE: usize = usize::MAX;
+/// Residual capacities below this are considered saturated.
+const EPSILON: f64 = 1e-12;
+
+/// A flow network which computes a minimum s-t cut with Dinic's algorithm.
+///
+/// The buffers are kept between uses so that building the graph for every hypothesis doesn't allocate.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MaxFlow {
+    /// The first edge leaving every node.
+    head: Vec<usize>,
+    /// The edges are stored in pairs so that the reverse of edge `e` is `e ^ 1`.
+    to: Vec<usize>,
+    capacity: Vec<f64>,
+    next: Vec<usize>,
+    level: Vec<usize>,
+    current: Vec<usize>,
+    queue: Vec<usize>,
+    path: Vec<usize>,
+}
+
+impl MaxFlow {
+    /// Clears the graph and adds `nodes` nodes without any edges.
+    pub(crate) fn reset(&mut self, nodes: usize) {
+        self.head.clear();
+        self.head.resize(nodes, NONE);
+        self.to.clear();
+        self.capacity.clear();
+        self.next.clear();
+    }
+
+    /// Adds an edge from `a` to `b` with capacity `forward` and from `b` to `a` with capacity `backward`.
+    pub(crate) fn add_edge(&mut self, a: usize, b: usize, forward: f64, backward: f64) {
+        for (from, to, capacity) in [(a, b, forward), (b, a, backward)] {
+            self.to.push(to);
+            self.capacity.push(capacity);
+            self.next.push(self.head[from]);
+            self.head[from] = self.to.len() - 1;
+        }
+    }
+
+    /// Computes the maximum flow from `source` to `sink`.
+    ///
+    /// Afterwards, [`MaxFlow::is_source_side`] tells which side of the minimum cut every node is on.
+    pub(crate) fn solve(&mut self, source: usize, sink: usize) -> f64 {
+        let mut flow = 0.0;
+        while self.build_levels(source, sink) {
+            self.current.clear();
+            self.current.extend_from_slice(&self.head);
+            flow += self.augment(source, sink);
+        }
+        flow
+    }
+
+    /// Whether `node` is reachable from the source in the residual graph after [`MaxFlow::solve`].
+    pub(crate) fn is_source_side(&self, node: usize) -> bool {
+        self.level[node] != NONE
+    }
+
+    /// Computes the distance of every node from the source in the residual graph.
+    ///
+    /// Returns `false` if the sink is unreachable, in which case the flow is maximal.
+    fn build_levels(&mut self, source: usize, sink: usize) -> bool {
+        self.level.clear();
+        self.level.resize(self.head.len(), NONE);
+        self.queue.clear();
+        self.level[source] = 0;
+        self.queue.push(source);
+        let mut front = 0;
+        while let Some(&node) = self.queue.get(front) {
+            front += 1;
+            let mut edge = self.head[node];
+            while edge != NONE {
+                let to = self.to[edge];
+                if self.capacity[edge] > EPSILON && self.level[to] == NONE {
+                    self.level[to] = self.level[node] + 1;
+                    self.queue.push(to);
+                }
+                edge = self.next[edge];
+            }
+        }
+        self.level[sink] != NONE
+    }
+
+    /// Finds a blocking flow in the level graph with an iterative depth-first search.
+    fn augment(&mut self, source: usize, sink: usize) -> f64 {
+        let mut flow = 0.0;
+        let mut node = source;
+        self.path.clear();
+        loop {
+            if node == sink {
+                let bottleneck = self
+                    .path
+                    .iter()
+                    .map(|&edge| self.capacity[edge])
+                    .fold(f64::INFINITY, f64::min);
+                for &edge in &self.path {
+                    self.capacity[edge] -= bottleneck;
+                    self.capacity[edge ^ 1] += bottleneck;
+                }
+                flow += bottleneck;
+                // Retreat to the tail of the first saturated edge.
+                let saturated = self
+                    .path
+                    .iter()
+                    .position(|&edge| self.capacity[edge] <= EPSILON)
+                    .unwrap_or(0);
+                self.path.truncate(saturated);
+                node = self.path.last().map_or(source, |&edge| self.to[edge]);
+                continue;
+            }
+
+            let mut advanced = false;
+            while self.current[node] != NONE {
+                let edge = self.current[node];
+                let to = self.to[edge];
+                if self.capacity[edge] > EPSILON
+                    && self.level[to] != NONE
+                    && self.level[to] == self.level[node] + 1
+                {
+                    self.path.push(edge);
+                    node = to;
+                    advanced = true;
+                    break;
+                }
+                self.current[node] = self.next[edge];
+            }
+            if !advanced {
+                if node == source {
+                    return flow;
+                }
+                // This node is a dead end, so remove it from the level graph and retreat.
+                self.level[node] = NONE;
+                let edge = self.path.pop().unwrap();
+                node = self.to[edge ^ 1];
+                self.current[node] = self.next[self.current[node]];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The flow network from Figure 26.1 of "Introduction to Algorithms" by Cormen et al.
+    fn clrs() -> MaxFlow {
+        let mut flow = MaxFlow::default();
+        flow.reset(6);
+        for (a, b, capacity) in [
+            (0, 1, 16.0),
+            (0, 2, 13.0),
+            (2, 1, 4.0),
+            (1, 3, 12.0),
+            (3, 2, 9.0),
+            (2, 4, 14.0),
+            (4, 3, 7.0),
+            (3, 5, 20.0),
+            (4, 5, 4.0),
+        ] {
+            flow.add_edge(a, b, capacity, 0.0);
+        }
+        flow
+    }
+
+    #[test]
+    fn solve_finds_the_minimum_cut() {
+        let mut flow = clrs();
+        assert_eq!(flow.solve(0, 5), 23.0);
+        let source_side: Vec<_> = (0..6).filter(|&node| flow.is_source_side(node)).collect();
+        assert_eq!(source_side, [0, 1, 2, 4]);
+    }
+
+    #[test]
+    fn solve_uses_backward_capacities() {
+        let mut flow = MaxFlow::default();
+        flow.reset(4);
+        flow.add_edge(0, 1, 3.0, 0.0);
+        flow.add_edge(2, 1, 1.0, 2.0);
+        flow.add_edge(2, 3, 5.0, 0.0);
+        assert_eq!(flow.solve(0, 3), 2.0);
+        assert!(flow.is_source_side(1));
+        assert!(!flow.is_source_side(2));
+    }
+
+    #[test]
+    fn solve_without_a_path_is_zero() {
+        let mut flow = MaxFlow::default();
+        flow.reset(3);
+        flow.add_edge(0, 1, 1.0, 0.0);
+        flow.add_edge(2, 1, 1.0, 0.0);
+        assert_eq!(flow.solve(0, 2), 0.0);
+        assert!(flow.is_source_side(1));
+        assert!(!flow.is_source_side(2));
+    }
+
+    #[test]
+    fn reset_clears_the_graph() {
+        let mut flow = clrs();
+        flow.solve(0, 5);
+        flow.reset(2);
+        flow.add_edge(0, 1, 1.5, 0.0);
+        assert_eq!(flow.solve(0, 1), 1.5);
+    }
+}
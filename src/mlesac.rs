@@ -0,0 +1,323 @@
+use crate::{
+    Adaptive, Consensus, ConsensusReport, Estimator, Ransac, ReportingConsensus, Sampler, Score,
+    Scorer, Sprt, Termination, Uniform,
+};
+use alloc::vec::Vec;
+use core::f64::consts::PI;
+use rand_core::RngCore;
+
+/// The MLESAC (maximum likelihood estimation sample consensus) algorithm.
+///
+/// This samples and terminates exactly like [`Ransac`], but scores each hypothesis by the likelihood of its
+/// residuals under a mixture of Gaussian inliers with standard deviation `sigma` and outliers uniformly
+/// distributed over `outlier_range`. The mixing parameter (the inlier ratio) is estimated with
+/// expectation-maximization for every hypothesis, and the hypothesis with the lowest negative log-likelihood
+/// wins. A datapoint is an inlier when it is more likely to be an inlier than an outlier under the mixture.
+///
+/// It is a [`Ransac`] with the [`MlesacScorer`], which keeps the residuals of the current hypothesis in a
+/// buffer that is reused between calls.
+#[derive(Clone, Debug)]
+pub struct Mlesac<R, S = Uniform, T = Adaptive> {
+    ransac: Ransac<R, S, MlesacScorer, T>,
+}
+
+impl<R> Mlesac<R>
+where
+    R: RngCore,
+{
+    /// Creates a new `Mlesac` for inlier residuals with standard deviation `sigma` and outlier residuals
+    /// spread uniformly over `outlier_range`.
+    ///
+    /// `rng` is used to draw the samples. By default, at most `1000` iterations are run and iteration stops
+    /// early once an all-inlier sample has been drawn with a confidence of `0.99`.
+    pub fn new(sigma: f64, outlier_range: f64, rng: R) -> Self {
+        Self {
+            ransac: Ransac::with_scorer(MlesacScorer::new(sigma, outlier_range), rng),
+        }
+    }
+}
+
+impl<R, S> Mlesac<R, S>
+where
+    R: RngCore,
+{
+    /// The maximum number of samples to draw.
+    ///
+    /// Default: `1000`
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            ransac: self.ransac.max_iterations(max_iterations),
+        }
+    }
+
+    /// The probability of having drawn at least one all-inlier sample at which iteration stops.
+    ///
+    /// Default: `0.99`
+    pub fn confidence(self, confidence: f64) -> Self {
+        Self {
+            ransac: self.ransac.confidence(confidence),
+        }
+    }
+}
+
+impl<R, S, T> Mlesac<R, S, T>
+where
+    R: RngCore,
+{
+    /// The standard deviation of the residuals of inliers.
+    ///
+    /// Default: specified in [`Mlesac::new`]
+    pub fn sigma(self, sigma: f64) -> Self {
+        Self {
+            ransac: self.ransac.map_scorer(|scorer| scorer.sigma(sigma)),
+        }
+    }
+
+    /// The width of the range over which the residuals of outliers are uniformly distributed.
+    ///
+    /// Default: specified in [`Mlesac::new`]
+    pub fn outlier_range(self, outlier_range: f64) -> Self {
+        Self {
+            ransac: self
+                .ransac
+                .map_scorer(|scorer| scorer.outlier_range(outlier_range)),
+        }
+    }
+
+    /// The number of expectation-maximization iterations used to estimate the inlier ratio of a hypothesis.
+    ///
+    /// Default: `5`
+    pub fn em_iterations(self, em_iterations: usize) -> Self {
+        Self {
+            ransac: self
+                .ransac
+                .map_scorer(|scorer| scorer.em_iterations(em_iterations)),
+        }
+    }
+
+    /// Verifies hypotheses with the [`Sprt`] so that bad ones are rejected early.
+    ///
+    /// Default: every hypothesis is evaluated on all of the data
+    pub fn sprt(self, sprt: Sprt) -> Self {
+        Self {
+            ransac: self.ransac.sprt(sprt),
+        }
+    }
+
+    /// The [`Sampler`] which draws the minimal samples.
+    ///
+    /// Default: [`Uniform`]
+    pub fn sampler<X>(self, sampler: X) -> Mlesac<R, X, T> {
+        Mlesac {
+            ransac: self.ransac.sampler(sampler),
+        }
+    }
+
+    /// The [`Termination`] criterion which decides when to stop drawing samples, instead of the maximum number
+    /// of iterations and the confidence.
+    ///
+    /// Default: [`Adaptive`] with a confidence of `0.99` and at most `1000` iterations
+    pub fn termination<X>(self, termination: X) -> Mlesac<R, S, X> {
+        Mlesac {
+            ransac: self.ransac.termination(termination),
+        }
+    }
+
+    /// The number of minimal samples rejected by [`Estimator::is_sample_valid`] during the last search.
+    pub fn rejected_samples(&self) -> usize {
+        self.ransac.rejected_samples()
+    }
+}
+
+impl<E, R, S, T, Data> Consensus<E, Data> for Mlesac<R, S, T>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    S: Sampler,
+    T: Termination,
+{
+    type Inliers = Vec<usize>;
+
+    fn model<I>(&mut self, estimator: &E, data: I) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.ransac.model(estimator, data)
+    }
+
+    fn model_inliers<I>(&mut self, estimator: &E, data: I) -> Option<(E::Model, Self::Inliers)>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.ransac.model_inliers(estimator, data)
+    }
+}
+
+impl<E, R, S, T, Data> ReportingConsensus<E, Data> for Mlesac<R, S, T>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    S: Sampler,
+    T: Termination,
+{
+    fn model_report<I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.ransac.model_report(estimator, data)
+    }
+}
+
+/// Scores a model by the likelihood of its residuals under the mixture of [`Mlesac`], where inliers are the
+/// datapoints which are more likely to be inliers than outliers under the mixture.
+///
+/// The residuals of the model are kept in a buffer which is reused between calls.
+#[derive(Clone, Debug)]
+pub struct MlesacScorer {
+    sigma: f64,
+    outlier_range: f64,
+    em_iterations: usize,
+    residuals: Vec<f64>,
+}
+
+impl MlesacScorer {
+    /// Creates a new `MlesacScorer` for inlier residuals with standard deviation `sigma` and outlier residuals
+    /// spread uniformly over `outlier_range`.
+    pub fn new(sigma: f64, outlier_range: f64) -> Self {
+        Self {
+            sigma,
+            outlier_range,
+            em_iterations: 5,
+            residuals: Vec::new(),
+        }
+    }
+
+    /// The standard deviation of the residuals of inliers.
+    ///
+    /// Default: specified in [`MlesacScorer::new`]
+    pub fn sigma(self, sigma: f64) -> Self {
+        Self { sigma, ..self }
+    }
+
+    /// The width of the range over which the residuals of outliers are uniformly distributed.
+    ///
+    /// Default: specified in [`MlesacScorer::new`]
+    pub fn outlier_range(self, outlier_range: f64) -> Self {
+        Self {
+            outlier_range,
+            ..self
+        }
+    }
+
+    /// The number of expectation-maximization iterations used to estimate the inlier ratio of a model.
+    ///
+    /// Default: `5`
+    pub fn em_iterations(self, em_iterations: usize) -> Self {
+        Self {
+            em_iterations,
+            ..self
+        }
+    }
+
+    fn inlier_density(&self, residual: f64) -> f64 {
+        let z = residual / self.sigma;
+        libm::exp(-0.5 * z * z) / (libm::sqrt(2.0 * PI) * self.sigma)
+    }
+
+    /// Estimates the inlier ratio of the buffered residuals with expectation-maximization.
+    fn inlier_ratio(&self) -> f64 {
+        let outlier_density = self.outlier_range.recip();
+        let mut ratio = 0.5;
+        for _ in 0..self.em_iterations {
+            let expected_inliers: f64 = self
+                .residuals
+                .iter()
+                .map(|&residual| {
+                    let inlier = ratio * self.inlier_density(residual);
+                    let outlier = (1.0 - ratio) * outlier_density;
+                    inlier / (inlier + outlier)
+                })
+                .sum();
+            ratio = expected_inliers / self.residuals.len() as f64;
+        }
+        ratio
+    }
+}
+
+impl Scorer for MlesacScorer {
+    fn score<I>(&mut self, residuals: I) -> Score
+    where
+        I: Iterator<Item = f64>,
+    {
+        self.residuals.clear();
+        self.residuals.extend(residuals);
+        let ratio = self.inlier_ratio();
+        let outlier_density = self.outlier_range.recip();
+        let (nll, inliers) = self
+            .residuals
+            .iter()
+            .fold((0.0, 0), |(nll, inliers), &residual| {
+                let inlier = ratio * self.inlier_density(residual);
+                let outlier = (1.0 - ratio) * outlier_density;
+                (
+                    nll - libm::log(inlier + outlier),
+                    inliers + (inlier > outlier) as usize,
+                )
+            });
+        Score {
+            value: -nll,
+            inliers,
+        }
+    }
+
+    fn inliers<I>(&mut self, residuals: I) -> Vec<usize>
+    where
+        I: Iterator<Item = f64>,
+    {
+        self.residuals.clear();
+        self.residuals.extend(residuals);
+        let ratio = self.inlier_ratio();
+        let outlier_density = self.outlier_range.recip();
+        self.residuals
+            .iter()
+            .enumerate()
+            .filter(|&(_, &residual)| {
+                ratio * self.inlier_density(residual) > (1.0 - ratio) * outlier_density
+            })
+            .map(|(ix, _)| ix)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{noisy_line, rng, LineEstimator, LINE};
+    use crate::Model;
+
+    #[test]
+    fn em_converges_to_the_inlier_ratio() {
+        // 30% of the residuals are inliers within 0.1 of the line, the rest are spread over 100.
+        let data = noisy_line(0, 30, 70);
+        let residuals = data.iter().map(|data| LINE.residual(data));
+        let mut scorer = MlesacScorer::new(0.06, 100.0).em_iterations(50);
+        let score = scorer.score(residuals.clone());
+        assert_eq!(score.inliers, 30);
+        let ratio = scorer.inlier_ratio();
+        assert!((ratio - 0.3).abs() < 0.01);
+        let mut scorer = scorer.em_iterations(100);
+        scorer.score(residuals);
+        assert!((scorer.inlier_ratio() - ratio).abs() < 1e-9);
+    }
+
+    #[test]
+    fn search_finds_the_most_likely_line() {
+        let data = noisy_line(0, 60, 140);
+        let (model, inliers) = Mlesac::new(0.06, 100.0, rng(0))
+            .model_inliers(&LineEstimator::default(), data.iter().copied())
+            .unwrap();
+        assert!((model.slope - LINE.slope).abs() < 0.1);
+        assert!((model.intercept - LINE.intercept).abs() < 0.5);
+        assert!(inliers.len() >= 50);
+    }
+}
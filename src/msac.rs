@@ -0,0 +1,185 @@
+use crate::{
+    Adaptive, Consensus, ConsensusReport, CountScorer, Estimator, Ransac, ReportingConsensus,
+    Sampler, Score, Scorer, Sprt, Termination, Uniform,
+};
+use alloc::vec::Vec;
+use rand_core::RngCore;
+
+/// The MSAC (M-estimator sample consensus) algorithm.
+///
+/// This samples and terminates exactly like [`Ransac`], but instead of counting inliers it scores each
+/// hypothesis with the truncated quadratic cost `min(residual^2, threshold^2)` summed over all of the data,
+/// keeping the hypothesis with the lowest cost. This takes into account how well the inliers fit rather than
+/// only how many there are. It is a [`Ransac`] with the [`MsacScorer`].
+///
+/// Apart from the buffer the sample is drawn into, which is kept between searches, searching for the model
+/// does not allocate. Only the list of inliers returned by [`Consensus::model_inliers`] is allocated.
+#[derive(Clone, Debug)]
+pub struct Msac<R, S = Uniform, T = Adaptive> {
+    ransac: Ransac<R, S, MsacScorer, T>,
+}
+
+impl<R> Msac<R>
+where
+    R: RngCore,
+{
+    /// Creates a new `Msac` which truncates the cost of a datapoint once its residual reaches `threshold`.
+    ///
+    /// `rng` is used to draw the samples. By default, at most `1000` iterations are run and iteration stops
+    /// early once an all-inlier sample has been drawn with a confidence of `0.99`.
+    pub fn new(threshold: f64, rng: R) -> Self {
+        Self {
+            ransac: Ransac::with_scorer(MsacScorer::new(threshold), rng),
+        }
+    }
+}
+
+impl<R, S> Msac<R, S>
+where
+    R: RngCore,
+{
+    /// The maximum number of samples to draw.
+    ///
+    /// Default: `1000`
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            ransac: self.ransac.max_iterations(max_iterations),
+        }
+    }
+
+    /// The probability of having drawn at least one all-inlier sample at which iteration stops.
+    ///
+    /// Default: `0.99`
+    pub fn confidence(self, confidence: f64) -> Self {
+        Self {
+            ransac: self.ransac.confidence(confidence),
+        }
+    }
+}
+
+impl<R, S, T> Msac<R, S, T>
+where
+    R: RngCore,
+{
+    /// The residual at which the cost of a datapoint is truncated and it is considered an outlier.
+    ///
+    /// Default: specified in [`Msac::new`]
+    pub fn threshold(self, threshold: f64) -> Self {
+        Self {
+            ransac: self.ransac.scorer(MsacScorer::new(threshold)),
+        }
+    }
+
+    /// Verifies hypotheses with the [`Sprt`] so that bad ones are rejected early.
+    ///
+    /// Default: every hypothesis is evaluated on all of the data
+    pub fn sprt(self, sprt: Sprt) -> Self {
+        Self {
+            ransac: self.ransac.sprt(sprt),
+        }
+    }
+
+    /// The [`Sampler`] which draws the minimal samples.
+    ///
+    /// Default: [`Uniform`]
+    pub fn sampler<X>(self, sampler: X) -> Msac<R, X, T> {
+        Msac {
+            ransac: self.ransac.sampler(sampler),
+        }
+    }
+
+    /// The [`Termination`] criterion which decides when to stop drawing samples, instead of the maximum number
+    /// of iterations and the confidence.
+    ///
+    /// Default: [`Adaptive`] with a confidence of `0.99` and at most `1000` iterations
+    pub fn termination<X>(self, termination: X) -> Msac<R, S, X> {
+        Msac {
+            ransac: self.ransac.termination(termination),
+        }
+    }
+
+    /// The number of minimal samples rejected by [`Estimator::is_sample_valid`] during the last search.
+    pub fn rejected_samples(&self) -> usize {
+        self.ransac.rejected_samples()
+    }
+}
+
+impl<E, R, S, T, Data> Consensus<E, Data> for Msac<R, S, T>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    S: Sampler,
+    T: Termination,
+{
+    type Inliers = Vec<usize>;
+
+    fn model<I>(&mut self, estimator: &E, data: I) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.ransac.model(estimator, data)
+    }
+
+    fn model_inliers<I>(&mut self, estimator: &E, data: I) -> Option<(E::Model, Self::Inliers)>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.ransac.model_inliers(estimator, data)
+    }
+}
+
+impl<E, R, S, T, Data> ReportingConsensus<E, Data> for Msac<R, S, T>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    S: Sampler,
+    T: Termination,
+{
+    fn model_report<I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.ransac.model_report(estimator, data)
+    }
+}
+
+/// Scores a model with the truncated quadratic cost of [`Msac`], where inliers are the datapoints with a residual
+/// below the threshold.
+#[derive(Copy, Clone, Debug)]
+pub struct MsacScorer {
+    threshold: f64,
+}
+
+impl MsacScorer {
+    /// Creates a new `MsacScorer` which truncates the cost of a datapoint once its residual reaches `threshold`.
+    pub fn new(threshold: f64) -> Self {
+        Self { threshold }
+    }
+}
+
+impl Scorer for MsacScorer {
+    fn score<I>(&mut self, residuals: I) -> Score
+    where
+        I: Iterator<Item = f64>,
+    {
+        let threshold = self.threshold;
+        let (cost, inliers) = residuals.fold((0.0, 0), |(cost, inliers), residual| {
+            if residual < threshold {
+                (cost + residual * residual, inliers + 1)
+            } else {
+                (cost + threshold * threshold, inliers)
+            }
+        });
+        Score {
+            value: -cost,
+            inliers,
+        }
+    }
+
+    fn inliers<I>(&mut self, residuals: I) -> Vec<usize>
+    where
+        I: Iterator<Item = f64>,
+    {
+        CountScorer::new(self.threshold).inliers(residuals)
+    }
+}
@@ -0,0 +1,260 @@
+use crate::kd_tree::{self, KdTree};
+use alloc::vec::Vec;
+
+/// A `Neighborhood` provides the neighbors of every datapoint, such as the datapoints which are spatially close to it.
+///
+/// Datapoints are identified by their index in the data passed to the consensus.
+pub trait Neighborhood {
+    /// Returns the indices of the neighbors of the datapoint at `index`, which shouldn't include `index` itself.
+    fn neighbors(&self, index: usize) -> &[usize];
+}
+
+/// A precomputed list of neighbors for every datapoint.
+impl Neighborhood for [Vec<usize>] {
+    fn neighbors(&self, index: usize) -> &[usize] {
+        self.get(index).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// A precomputed list of neighbors for every datapoint.
+impl Neighborhood for Vec<Vec<usize>> {
+    fn neighbors(&self, index: usize) -> &[usize] {
+        self.as_slice().neighbors(index)
+    }
+}
+
+impl<N> Neighborhood for &N
+where
+    N: Neighborhood + ?Sized,
+{
+    fn neighbors(&self, index: usize) -> &[usize] {
+        (**self).neighbors(index)
+    }
+}
+
+/// The neighbors of every datapoint, found from the position of every datapoint in `D` dimensions.
+///
+/// This is used to provide the [`Neighborhood`] of data which doesn't come with one, such as keypoint
+/// matches, where the positions would be the keypoints in one of the images.
+#[derive(Clone, Debug, Default)]
+pub struct Neighbors {
+    /// The neighbors of datapoint `i` are `indices[offsets[i]..offsets[i + 1]]`.
+    offsets: Vec<usize>,
+    indices: Vec<usize>,
+}
+
+impl Neighbors {
+    /// Finds the neighbors of every point which are within `radius` of it.
+    ///
+    /// The points are bucketed into a grid with cells of size `radius`, so only the adjacent cells are searched.
+    /// This is efficient in few dimensions, but the number of adjacent cells grows as `3^D`. Cells beyond the range
+    /// of `i64` are merged into the outermost ones, which only makes the search slower, and points with non-finite
+    /// coordinates have no neighbors.
+    pub fn within_radius<const D: usize>(points: &[[f64; D]], radius: f64) -> Self {
+        assert!(radius > 0.0, "the neighborhood radius must be positive");
+        let cell = |point: &[f64; D]| point.map(|x| libm::floor(x / radius) as i64);
+        let mut cells: Vec<([i64; D], usize)> = points
+            .iter()
+            .enumerate()
+            .map(|(ix, point)| (cell(point), ix))
+            .collect();
+        cells.sort_unstable();
+
+        let squared_radius = radius * radius;
+        let adjacent = 3usize.pow(D as u32);
+        let mut neighbors = Self::default();
+        neighbors.offsets.push(0);
+        for (ix, point) in points.iter().enumerate() {
+            let center = cell(point);
+            'adjacent: for mut offset in 0..adjacent {
+                let mut target = center;
+                for coordinate in &mut target {
+                    // There are no cells past the outermost ones, which hold all of the points beyond them.
+                    match coordinate.checked_add((offset % 3) as i64 - 1) {
+                        Some(adjacent) => *coordinate = adjacent,
+                        None => continue 'adjacent,
+                    }
+                    offset /= 3;
+                }
+                let start = cells.partition_point(|&(cell, _)| cell < target);
+                let end = start + cells[start..].partition_point(|&(cell, _)| cell == target);
+                neighbors
+                    .indices
+                    .extend(
+                        cells[start..end]
+                            .iter()
+                            .map(|&(_, other)| other)
+                            .filter(|&other| {
+                                other != ix
+                                    && kd_tree::squared_distance(point, &points[other])
+                                        <= squared_radius
+                            }),
+                    );
+            }
+            neighbors.offsets.push(neighbors.indices.len());
+        }
+        neighbors
+    }
+
+    /// Finds the `k` nearest neighbors of every point, or all of the other points if there are fewer.
+    ///
+    /// The nearest neighbors are found with a kd-tree, ordered from nearest to farthest.
+    pub fn nearest<const D: usize>(points: &[[f64; D]], k: usize) -> Self {
+        let tree = KdTree::new(points);
+        let mut nearest = Vec::with_capacity(k + 1);
+        let mut neighbors = Self::default();
+        neighbors.offsets.push(0);
+        for ix in 0..points.len() {
+            tree.nearest(ix, k, &mut nearest);
+            neighbors
+                .indices
+                .extend(nearest.iter().map(|&(_, other)| other));
+            neighbors.offsets.push(neighbors.indices.len());
+        }
+        neighbors
+    }
+
+    /// The number of datapoints.
+    pub fn len(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+
+    /// Whether there are no datapoints.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Neighborhood for Neighbors {
+    fn neighbors(&self, index: usize) -> &[usize] {
+        if index < self.len() {
+            &self.indices[self.offsets[index]..self.offsets[index + 1]]
+        } else {
+            &[]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{rng, uniform};
+
+    fn random_points<const D: usize>(seed: u64, len: usize) -> Vec<[f64; D]> {
+        let mut rng = rng(seed);
+        (0..len)
+            .map(|_| [(); D].map(|()| uniform(&mut rng, -10.0, 10.0)))
+            .collect()
+    }
+
+    /// The other points within `radius` of every point, in order.
+    fn brute_force_within_radius<const D: usize>(
+        points: &[[f64; D]],
+        radius: f64,
+    ) -> Vec<Vec<usize>> {
+        (0..points.len())
+            .map(|ix| {
+                (0..points.len())
+                    .filter(|&other| {
+                        other != ix
+                            && kd_tree::squared_distance(&points[ix], &points[other])
+                                <= radius * radius
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The `k` nearest other points of every point, from nearest to farthest.
+    fn brute_force_nearest<const D: usize>(points: &[[f64; D]], k: usize) -> Vec<Vec<usize>> {
+        (0..points.len())
+            .map(|ix| {
+                let mut others: Vec<usize> =
+                    (0..points.len()).filter(|&other| other != ix).collect();
+                others.sort_by(|&a, &b| {
+                    kd_tree::squared_distance(&points[ix], &points[a])
+                        .total_cmp(&kd_tree::squared_distance(&points[ix], &points[b]))
+                });
+                others.truncate(k);
+                others
+            })
+            .collect()
+    }
+
+    fn sorted(neighbors: &Neighbors, index: usize) -> Vec<usize> {
+        let mut neighbors = neighbors.neighbors(index).to_vec();
+        neighbors.sort_unstable();
+        neighbors
+    }
+
+    #[test]
+    fn within_radius_matches_brute_force() {
+        let points = random_points::<2>(0, 200);
+        for &radius in &[0.5, 1.5, 4.0] {
+            let neighbors = Neighbors::within_radius(&points, radius);
+            assert_eq!(neighbors.len(), points.len());
+            for (ix, expected) in brute_force_within_radius(&points, radius)
+                .iter()
+                .enumerate()
+            {
+                assert_eq!(&sorted(&neighbors, ix), expected);
+            }
+        }
+        let points = random_points::<3>(1, 200);
+        let neighbors = Neighbors::within_radius(&points, 3.0);
+        for (ix, expected) in brute_force_within_radius(&points, 3.0).iter().enumerate() {
+            assert_eq!(&sorted(&neighbors, ix), expected);
+        }
+    }
+
+    #[test]
+    fn nearest_matches_brute_force() {
+        let points = random_points::<2>(0, 200);
+        for &k in &[0, 1, 5, 20] {
+            let neighbors = Neighbors::nearest(&points, k);
+            for (ix, expected) in brute_force_nearest(&points, k).iter().enumerate() {
+                assert_eq!(neighbors.neighbors(ix), expected.as_slice());
+            }
+        }
+        let points = random_points::<3>(1, 200);
+        let neighbors = Neighbors::nearest(&points, 8);
+        for (ix, expected) in brute_force_nearest(&points, 8).iter().enumerate() {
+            assert_eq!(neighbors.neighbors(ix), expected.as_slice());
+        }
+        // With fewer points than neighbors, all of the other points are neighbors.
+        let neighbors = Neighbors::nearest(&points[..4], 8);
+        assert_eq!(sorted(&neighbors, 0), [1, 2, 3]);
+    }
+
+    #[test]
+    fn within_radius_handles_extreme_coordinates() {
+        let points = [
+            [0.0, 0.0],
+            [0.5, 0.0],
+            [f64::MAX, 0.0],
+            [f64::MAX, 0.5],
+            [-f64::MAX, f64::MIN_POSITIVE],
+            [f64::INFINITY, 0.0],
+            [f64::NAN, 0.0],
+        ];
+        let neighbors = Neighbors::within_radius(&points, 1.0);
+        for (ix, expected) in brute_force_within_radius(&points, 1.0).iter().enumerate() {
+            assert_eq!(&sorted(&neighbors, ix), expected);
+        }
+        assert_eq!(neighbors.neighbors(2), [3]);
+        assert!(neighbors.neighbors(5).is_empty());
+        assert!(neighbors.neighbors(6).is_empty());
+    }
+
+    #[test]
+    fn points_without_dimensions_coincide() {
+        let points = [[]; 4];
+        let neighbors = Neighbors::within_radius(&points, 1.0);
+        assert_eq!(sorted(&neighbors, 0), [1, 2, 3]);
+        let neighbors = Neighbors::nearest(&points, 2);
+        for ix in 0..points.len() {
+            assert_eq!(neighbors.neighbors(ix).len(), 2);
+            assert!(!neighbors.neighbors(ix).contains(&ix));
+        }
+    }
+}
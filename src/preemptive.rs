@@ -0,0 +1,462 @@
+use crate::sample::{SampleBuffer, Select};
+use crate::termination::achieved_confidence;
+use crate::{
+    Consensus, ConsensusReport, Estimator, MaxIterations, Model, MsacScorer, ReportingConsensus,
+    Sampler, Scorer, Termination, TerminationReason, Uniform,
+};
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use rand_core::RngCore;
+
+/// Preemptive RANSAC from the paper "Preemptive RANSAC for Live Structure and Motion Estimation" by Nistér.
+///
+/// Rather than adapting the number of iterations to the data, a fixed number of hypotheses is generated up front
+/// and evaluated breadth-first on blocks of data. After every block the worst half of the hypotheses is discarded,
+/// until only one hypothesis remains or the data runs out. This bounds the run time, which makes it suitable for
+/// real-time applications with a fixed time budget. An additional [`Termination`] criterion, such as a
+/// [`Cancellation`](crate::Cancellation), can stop generating hypotheses sooner.
+///
+/// Hypotheses are scored with the truncated quadratic cost of [`Msac`](crate::Msac) by default, but any [`Scorer`]
+/// whose score is a sum over the datapoints, such as the [`CountScorer`](crate::CountScorer), can be used instead.
+/// Since the hypotheses are evaluated on the data in order, the data must be shuffled.
+#[derive(Clone, Debug)]
+pub struct PreemptiveRansac<R, S = Uniform, C = MsacScorer, T = MaxIterations> {
+    scorer: C,
+    hypotheses: usize,
+    block_size: usize,
+    termination: T,
+    rng: R,
+    sampler: S,
+    rejected_samples: usize,
+}
+
+impl<R> PreemptiveRansac<R>
+where
+    R: RngCore,
+{
+    /// Creates a new `PreemptiveRansac` which considers a datapoint an inlier when its residual is below
+    /// `threshold`.
+    ///
+    /// `rng` is used to draw the samples. By default, `500` hypotheses are generated and they are halved
+    /// after every block of `100` datapoints.
+    pub fn new(threshold: f64, rng: R) -> Self {
+        Self {
+            scorer: MsacScorer::new(threshold),
+            hypotheses: 500,
+            block_size: 100,
+            termination: MaxIterations::new(usize::MAX),
+            rng,
+            sampler: Uniform,
+            rejected_samples: 0,
+        }
+    }
+}
+
+impl<R, S, T> PreemptiveRansac<R, S, MsacScorer, T>
+where
+    R: RngCore,
+{
+    /// The residual below which a datapoint is considered an inlier.
+    ///
+    /// Default: specified in [`PreemptiveRansac::new`]
+    pub fn threshold(self, threshold: f64) -> Self {
+        Self {
+            scorer: MsacScorer::new(threshold),
+            ..self
+        }
+    }
+}
+
+impl<R, S, C, T> PreemptiveRansac<R, S, C, T>
+where
+    R: RngCore,
+{
+    /// The number of samples to draw up front.
+    ///
+    /// Default: `500`
+    pub fn hypotheses(self, hypotheses: usize) -> Self {
+        Self { hypotheses, ..self }
+    }
+
+    /// The number of datapoints evaluated between halving the hypotheses.
+    ///
+    /// Default: `100`
+    pub fn block_size(self, block_size: usize) -> Self {
+        Self { block_size, ..self }
+    }
+
+    /// The [`Sampler`] which draws the minimal samples.
+    ///
+    /// Default: [`Uniform`]
+    pub fn sampler<X>(self, sampler: X) -> PreemptiveRansac<R, X, C, T> {
+        PreemptiveRansac {
+            scorer: self.scorer,
+            hypotheses: self.hypotheses,
+            block_size: self.block_size,
+            termination: self.termination,
+            rng: self.rng,
+            sampler,
+            rejected_samples: self.rejected_samples,
+        }
+    }
+
+    /// An additional [`Termination`] criterion which can stop drawing samples before all of them are drawn.
+    /// The hypotheses generated so far are still evaluated, unless the criterion interrupts the search, such as a
+    /// [`Cancellation`](crate::Cancellation), in which case the best hypothesis on the blocks evaluated so far wins.
+    /// Since the hypotheses are only scored afterwards, criteria which depend on the best hypothesis, such as
+    /// [`Confidence`](crate::Confidence), never stop it.
+    ///
+    /// Default: no additional criterion
+    pub fn termination<X>(self, termination: X) -> PreemptiveRansac<R, S, C, X> {
+        PreemptiveRansac {
+            scorer: self.scorer,
+            hypotheses: self.hypotheses,
+            block_size: self.block_size,
+            termination,
+            rng: self.rng,
+            sampler: self.sampler,
+            rejected_samples: self.rejected_samples,
+        }
+    }
+
+    /// The [`Scorer`] which scores the hypotheses and decides which datapoints are inliers. The hypotheses are
+    /// compared by the sum of the scores of the datapoints they were evaluated on, so the score of the data must
+    /// be the sum of the scores of the datapoints.
+    ///
+    /// Default: [`MsacScorer`] with the threshold specified in [`PreemptiveRansac::new`]
+    pub fn scorer<D>(self, scorer: D) -> PreemptiveRansac<R, S, D, T> {
+        PreemptiveRansac {
+            scorer,
+            hypotheses: self.hypotheses,
+            block_size: self.block_size,
+            termination: self.termination,
+            rng: self.rng,
+            sampler: self.sampler,
+            rejected_samples: self.rejected_samples,
+        }
+    }
+
+    /// The number of minimal samples rejected by [`Estimator::is_sample_valid`] during the last search.
+    pub fn rejected_samples(&self) -> usize {
+        self.rejected_samples
+    }
+}
+
+impl<R, S, C, T> PreemptiveRansac<R, S, C, T>
+where
+    R: RngCore,
+    S: Sampler,
+    C: Scorer,
+    T: Termination,
+{
+    /// Runs the search and reports the best model, without its inliers, score, or confidence.
+    fn search<E, Data, I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        E: Estimator<Data>,
+        I: Iterator<Item = Data> + Clone,
+    {
+        let len = data.clone().count();
+        self.rejected_samples = 0;
+        if len < E::MIN_SAMPLES {
+            return ConsensusReport::too_few_data();
+        }
+        let mut hypotheses: Vec<(E::Model, f64)> = Vec::with_capacity(self.hypotheses);
+        let mut generated = 0;
+        let mut rejected_hypotheses = 0;
+        self.sampler.reset();
+        self.termination.reset();
+        let mut iterations = 0;
+        let mut stopped = false;
+        let mut indices = SampleBuffer::new(E::MIN_SAMPLES);
+        while iterations < self.hypotheses {
+            if self.termination.should_stop(iterations) {
+                stopped = true;
+                break;
+            }
+            iterations += 1;
+            if !self.sampler.sample(&mut self.rng, len, &mut indices) {
+                continue;
+            }
+            let sample = Select::new(data.clone(), &indices);
+            if !estimator.is_sample_valid(sample.clone()) {
+                self.rejected_samples += 1;
+                continue;
+            }
+            for model in estimator.estimate(sample.clone()) {
+                generated += 1;
+                if estimator.is_model_valid(&model, sample.clone()) {
+                    hypotheses.push((model, 0.0));
+                } else {
+                    rejected_hypotheses += 1;
+                }
+            }
+        }
+
+        let candidates = hypotheses.len();
+        let block_size = self.block_size.max(1);
+        for (ix, data) in data.enumerate() {
+            if hypotheses.len() <= 1 {
+                break;
+            }
+            if ix % block_size == 0 && self.termination.is_interrupted() {
+                stopped = true;
+                break;
+            }
+            for (model, score) in &mut hypotheses {
+                *score += self
+                    .scorer
+                    .score(core::iter::once(model.residual(&data)))
+                    .value;
+            }
+            let evaluated = ix + 1;
+            if evaluated % block_size == 0 {
+                let keep = kept_hypotheses(candidates, evaluated / block_size);
+                if keep < hypotheses.len() {
+                    hypotheses.select_nth_unstable_by(keep, |a, b| by_score(b, a));
+                    hypotheses.truncate(keep);
+                }
+            }
+        }
+        ConsensusReport {
+            model: hypotheses
+                .into_iter()
+                .max_by(by_score)
+                .map(|(model, _)| model),
+            inliers: Vec::new(),
+            score: None,
+            iterations,
+            hypotheses: generated,
+            rejected_samples: self.rejected_samples,
+            rejected_hypotheses,
+            termination: if stopped {
+                self.termination.reason()
+            } else {
+                TerminationReason::MaxIterations
+            },
+            confidence: 0.0,
+        }
+    }
+}
+
+/// The number of hypotheses out of `candidates` which are still evaluated after `blocks` blocks of data, which
+/// halves after every block but never drops below one.
+pub(crate) fn kept_hypotheses(candidates: usize, blocks: usize) -> usize {
+    if blocks < usize::BITS as usize {
+        (candidates >> blocks).max(1)
+    } else {
+        1
+    }
+}
+
+impl<E, R, S, C, T, Data> Consensus<E, Data> for PreemptiveRansac<R, S, C, T>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    S: Sampler,
+    C: Scorer,
+    T: Termination,
+{
+    type Inliers = Vec<usize>;
+
+    fn model<I>(&mut self, estimator: &E, data: I) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.search(estimator, data).model
+    }
+
+    fn model_inliers<I>(&mut self, estimator: &E, data: I) -> Option<(E::Model, Self::Inliers)>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let model = self.model(estimator, data.clone())?;
+        let inliers = self.scorer.inliers(data.map(|data| model.residual(&data)));
+        Some((model, inliers))
+    }
+}
+
+impl<E, R, S, C, T, Data> ReportingConsensus<E, Data> for PreemptiveRansac<R, S, C, T>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    S: Sampler,
+    C: Scorer,
+    T: Termination,
+{
+    fn model_report<I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let mut report = self.search(estimator, data.clone());
+        if let Some(model) = &report.model {
+            let len = data.clone().count();
+            report.inliers = self
+                .scorer
+                .inliers(data.clone().map(|data| model.residual(&data)));
+            // The hypotheses are only compared on part of the data, so score the best one on all of it.
+            report.score = Some(
+                self.scorer
+                    .score(data.map(|data| model.residual(&data)))
+                    .value,
+            );
+            report.confidence = achieved_confidence(
+                report.inliers.len() as f64 / len as f64,
+                E::MIN_SAMPLES,
+                report.iterations,
+            );
+        }
+        report
+    }
+}
+
+fn by_score<M>(a: &(M, f64), b: &(M, f64)) -> Ordering {
+    a.1.total_cmp(&b.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{noisy_line, LineEstimator, LINE};
+    use crate::{Cancellation, CountScorer};
+    use core::cell::Cell;
+    use rand_core::SeedableRng;
+    use rand_pcg::Pcg64;
+
+    /// A model whose residual is the distance of a number from it, which counts how often it is evaluated.
+    struct Point<'a> {
+        value: f64,
+        residuals: &'a Cell<usize>,
+    }
+
+    impl Model<f64> for Point<'_> {
+        fn residual(&self, data: &f64) -> f64 {
+            self.residuals.set(self.residuals.get() + 1);
+            libm::fabs(data - self.value)
+        }
+    }
+
+    /// Estimates two points from every datapoint, so that there are twice as many hypotheses as samples.
+    struct TwoPoints<'a> {
+        residuals: &'a Cell<usize>,
+    }
+
+    impl<'a> Estimator<f64> for TwoPoints<'a> {
+        type Model = Point<'a>;
+        type ModelIter = [Point<'a>; 2];
+        const MIN_SAMPLES: usize = 1;
+
+        fn estimate<I>(&self, mut data: I) -> Self::ModelIter
+        where
+            I: Iterator<Item = f64> + Clone,
+        {
+            let value = data.next().unwrap();
+            [value, value + 0.5].map(|value| Point {
+                value,
+                residuals: self.residuals,
+            })
+        }
+    }
+
+    /// Counts the residuals evaluated while searching `len` datapoints with `consensus`.
+    fn count_residuals<T>(
+        consensus: &mut PreemptiveRansac<Pcg64, Uniform, MsacScorer, T>,
+        len: usize,
+    ) -> usize
+    where
+        T: Termination,
+    {
+        let residuals = Cell::new(0);
+        let estimator = TwoPoints {
+            residuals: &residuals,
+        };
+        let data = (0..len).map(|ix| ix as f64);
+        assert!(consensus.model(&estimator, data).is_some());
+        residuals.get()
+    }
+
+    #[test]
+    fn kept_hypotheses_halves_after_every_block() {
+        assert_eq!(kept_hypotheses(100, 0), 100);
+        assert_eq!(kept_hypotheses(100, 1), 50);
+        assert_eq!(kept_hypotheses(100, 2), 25);
+        assert_eq!(kept_hypotheses(100, 3), 12);
+        assert_eq!(kept_hypotheses(100, 6), 1);
+        assert_eq!(kept_hypotheses(100, 7), 1);
+        assert_eq!(kept_hypotheses(100, 1000), 1);
+        assert_eq!(kept_hypotheses(0, 0), 1);
+    }
+
+    #[test]
+    fn search_halves_the_generated_hypotheses() {
+        // 8 samples produce 16 hypotheses, which are evaluated on one datapoint each time they are halved.
+        let mut consensus = PreemptiveRansac::new(1.0, Pcg64::seed_from_u64(0))
+            .hypotheses(8)
+            .block_size(1);
+        assert_eq!(count_residuals(&mut consensus, 100), 16 + 8 + 4 + 2);
+        let mut consensus = PreemptiveRansac::new(1.0, Pcg64::seed_from_u64(0))
+            .hypotheses(8)
+            .block_size(3);
+        assert_eq!(count_residuals(&mut consensus, 100), 3 * (16 + 8 + 4 + 2));
+    }
+
+    #[test]
+    fn search_stops_once_one_hypothesis_remains() {
+        let mut consensus = PreemptiveRansac::new(1.0, Pcg64::seed_from_u64(0))
+            .hypotheses(1)
+            .block_size(1);
+        assert_eq!(count_residuals(&mut consensus, 100), 2);
+        // Without enough data to halve them down to one, every hypothesis is evaluated on all of the data.
+        let mut consensus = PreemptiveRansac::new(1.0, Pcg64::seed_from_u64(0))
+            .hypotheses(8)
+            .block_size(100);
+        assert_eq!(count_residuals(&mut consensus, 50), 16 * 50);
+    }
+
+    #[test]
+    fn search_stops_generating_on_termination() {
+        let mut consensus = PreemptiveRansac::new(1.0, Pcg64::seed_from_u64(0))
+            .hypotheses(8)
+            .block_size(1)
+            .termination(MaxIterations::new(2));
+        assert_eq!(count_residuals(&mut consensus, 100), 4 + 2);
+        let residuals = Cell::new(0);
+        let estimator = TwoPoints {
+            residuals: &residuals,
+        };
+        let report = consensus.model_report(&estimator, (0..100).map(|ix| ix as f64));
+        assert_eq!(report.iterations, 2);
+        assert_eq!(report.hypotheses, 4);
+        assert_eq!(report.termination, TerminationReason::MaxIterations);
+    }
+
+    #[test]
+    fn cancellation_interrupts_the_evaluation() {
+        let residuals = Cell::new(0);
+        let estimator = TwoPoints {
+            residuals: &residuals,
+        };
+        // Cancel once the 16 hypotheses have been evaluated on the first block.
+        let mut consensus = PreemptiveRansac::new(1.0, Pcg64::seed_from_u64(0))
+            .hypotheses(8)
+            .block_size(1)
+            .termination(Cancellation::new(|| residuals.get() >= 16));
+        let model = consensus.model(&estimator, (0..100).map(|ix| ix as f64));
+        assert!(model.is_some());
+        assert_eq!(residuals.get(), 16);
+    }
+
+    #[test]
+    fn search_scores_with_the_scorer() {
+        let data = noisy_line(0, 60, 140);
+        let mut scorer = CountScorer::new(0.2);
+        let report = PreemptiveRansac::new(0.2, Pcg64::seed_from_u64(0))
+            .block_size(20)
+            .scorer(scorer)
+            .model_report(&LineEstimator::default(), data.iter().copied());
+        let model = report.model.unwrap();
+        assert!((model.slope - LINE.slope).abs() < 0.1);
+        let residuals = data.iter().map(|data| model.residual(data));
+        assert_eq!(report.score, Some(scorer.score(residuals.clone()).value));
+        assert_eq!(report.inliers, scorer.inliers(residuals));
+    }
+}
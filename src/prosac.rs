@@ -0,0 +1,607 @@
+use crate::sample::{Growth, SampleBuffer, Select};
+use crate::termination::{achieved_confidence, prosac_required_iterations, BestHypothesis};
+use crate::{
+    Consensus, ConsensusReport, CountScorer, Estimator, MaxIterations, Model, ReportingConsensus,
+    Sampler, Scorer, Sprt, Termination, TerminationReason, Verification,
+};
+use alloc::vec::Vec;
+use rand_core::RngCore;
+
+/// The PROSAC (progressive sample consensus) algorithm.
+///
+/// PROSAC exploits a ranking of the data by quality, such as descriptor distance for feature matches.
+/// Samples are first drawn from the few top-ranked datapoints and the pool they are drawn from grows
+/// progressively, so that PROSAC draws the same samples as [`Ransac`](crate::Ransac) in the worst case,
+/// but usually finds a good model much sooner. The hypothesis with the most inliers wins, unless the hypotheses
+/// are scored by another [`Scorer`], whose inliers then drive the stopping criteria.
+///
+/// Iteration stops once there is a pool of top-ranked data whose inliers are unlikely to be supporting
+/// the model by chance (non-randomness) and from which an all-inlier sample has been drawn with the configured
+/// confidence (maximality). An additional [`Termination`] criterion, such as a
+/// [`Cancellation`](crate::Cancellation), can stop it sooner.
+///
+/// When used through [`Consensus`], the data must be sorted from highest to lowest quality. Unsorted data can be
+/// used along with a parallel iterator of qualities through [`Prosac::model_with_quality`] and
+/// [`Prosac::model_inliers_with_quality`]. The data is never shuffled, so don't shuffle it either.
+#[derive(Clone, Debug)]
+pub struct Prosac<R, C = CountScorer, T = MaxIterations> {
+    scorer: C,
+    max_iterations: usize,
+    confidence: f64,
+    growth_samples: usize,
+    random_support: f64,
+    termination: T,
+    sprt: Option<Sprt>,
+    rng: R,
+    rejected_samples: usize,
+    order: Vec<usize>,
+    quality: Vec<f64>,
+    is_inlier: Vec<bool>,
+}
+
+impl<R> Prosac<R>
+where
+    R: RngCore,
+{
+    /// Creates a new `Prosac` which considers a datapoint an inlier when its residual is below `threshold`.
+    ///
+    /// `rng` is used to draw the samples. By default, at most `1000` iterations are run and iteration stops
+    /// early once an all-inlier sample has been drawn with a confidence of `0.99`.
+    pub fn new(threshold: f64, rng: R) -> Self {
+        Self {
+            scorer: CountScorer::new(threshold),
+            max_iterations: 1000,
+            confidence: 0.99,
+            growth_samples: 200_000,
+            random_support: 0.05,
+            termination: MaxIterations::new(usize::MAX),
+            sprt: None,
+            rng,
+            rejected_samples: 0,
+            order: Vec::new(),
+            quality: Vec::new(),
+            is_inlier: Vec::new(),
+        }
+    }
+}
+
+impl<R, T> Prosac<R, CountScorer, T>
+where
+    R: RngCore,
+{
+    /// The residual below which a datapoint is considered an inlier.
+    ///
+    /// Default: specified in [`Prosac::new`]
+    pub fn threshold(self, threshold: f64) -> Self {
+        Self {
+            scorer: CountScorer::new(threshold),
+            ..self
+        }
+    }
+}
+
+impl<R, C, T> Prosac<R, C, T>
+where
+    R: RngCore,
+{
+    /// The maximum number of samples to draw.
+    ///
+    /// Default: `1000`
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            max_iterations,
+            ..self
+        }
+    }
+
+    /// The probability of having drawn at least one all-inlier sample at which iteration stops.
+    ///
+    /// Default: `0.99`
+    pub fn confidence(self, confidence: f64) -> Self {
+        Self { confidence, ..self }
+    }
+
+    /// The number of samples after which samples are drawn from all of the data, as in RANSAC.
+    ///
+    /// This controls how fast the pool of top-ranked data grows. It is `T_N` in the PROSAC paper.
+    ///
+    /// Default: `200_000`
+    pub fn growth_samples(self, growth_samples: usize) -> Self {
+        Self {
+            growth_samples,
+            ..self
+        }
+    }
+
+    /// The probability that an outlier happens to support an incorrect model.
+    ///
+    /// This is used by the non-randomness stopping criterion. It is `β` in the PROSAC paper.
+    ///
+    /// Default: `0.05`
+    pub fn random_support(self, random_support: f64) -> Self {
+        Self {
+            random_support,
+            ..self
+        }
+    }
+
+    /// Verifies hypotheses with the [`Sprt`] so that bad ones are rejected early.
+    ///
+    /// Default: every hypothesis is evaluated on all of the data
+    pub fn sprt(self, sprt: Sprt) -> Self {
+        Self {
+            sprt: Some(sprt),
+            ..self
+        }
+    }
+
+    /// An additional [`Termination`] criterion which can stop the search before the PROSAC stopping criteria or
+    /// the maximum number of iterations do.
+    ///
+    /// Default: no additional criterion
+    pub fn termination<X>(self, termination: X) -> Prosac<R, C, X> {
+        Prosac {
+            scorer: self.scorer,
+            max_iterations: self.max_iterations,
+            confidence: self.confidence,
+            growth_samples: self.growth_samples,
+            random_support: self.random_support,
+            termination,
+            sprt: self.sprt,
+            rng: self.rng,
+            rejected_samples: self.rejected_samples,
+            order: self.order,
+            quality: self.quality,
+            is_inlier: self.is_inlier,
+        }
+    }
+
+    /// The [`Scorer`] which scores the hypotheses and decides which datapoints are inliers.
+    ///
+    /// Default: [`CountScorer`] with the threshold specified in [`Prosac::new`]
+    pub fn scorer<D>(self, scorer: D) -> Prosac<R, D, T> {
+        Prosac {
+            scorer,
+            max_iterations: self.max_iterations,
+            confidence: self.confidence,
+            growth_samples: self.growth_samples,
+            random_support: self.random_support,
+            termination: self.termination,
+            sprt: self.sprt,
+            rng: self.rng,
+            rejected_samples: self.rejected_samples,
+            order: self.order,
+            quality: self.quality,
+            is_inlier: self.is_inlier,
+        }
+    }
+
+    /// The number of minimal samples rejected by [`Estimator::is_sample_valid`] during the last search.
+    pub fn rejected_samples(&self) -> usize {
+        self.rejected_samples
+    }
+}
+
+impl<R, C, T> Prosac<R, C, T>
+where
+    R: RngCore,
+    C: Scorer,
+    T: Termination,
+{
+    /// Finds a model from `data` paired with one quality per datapoint, where a higher quality
+    /// means a datapoint is more likely to be an inlier. The data doesn't need to be sorted.
+    ///
+    /// Panics if there isn't exactly one quality per datapoint.
+    pub fn model_with_quality<E, Data, I, Q>(
+        &mut self,
+        estimator: &E,
+        data: I,
+        quality: Q,
+    ) -> Option<E::Model>
+    where
+        E: Estimator<Data>,
+        I: Iterator<Item = Data> + Clone,
+        Q: IntoIterator<Item = f64>,
+    {
+        self.sort_by_quality(quality);
+        self.search(estimator, data, true).model
+    }
+
+    /// Finds a model and its inliers from `data` paired with one quality per datapoint, where a higher
+    /// quality means a datapoint is more likely to be an inlier. The data doesn't need to be sorted.
+    ///
+    /// Panics if there isn't exactly one quality per datapoint.
+    pub fn model_inliers_with_quality<E, Data, I, Q>(
+        &mut self,
+        estimator: &E,
+        data: I,
+        quality: Q,
+    ) -> Option<(E::Model, Vec<usize>)>
+    where
+        E: Estimator<Data>,
+        I: Iterator<Item = Data> + Clone,
+        Q: IntoIterator<Item = f64>,
+    {
+        let report = self.model_report_with_quality(estimator, data, quality);
+        Some((report.model?, report.inliers))
+    }
+
+    /// Reports the result of a search on `data` paired with one quality per datapoint, where a higher
+    /// quality means a datapoint is more likely to be an inlier. The data doesn't need to be sorted.
+    ///
+    /// Panics if there isn't exactly one quality per datapoint.
+    pub fn model_report_with_quality<E, Data, I, Q>(
+        &mut self,
+        estimator: &E,
+        data: I,
+        quality: Q,
+    ) -> ConsensusReport<E::Model>
+    where
+        E: Estimator<Data>,
+        I: Iterator<Item = Data> + Clone,
+        Q: IntoIterator<Item = f64>,
+    {
+        self.sort_by_quality(quality);
+        self.report(estimator, data, true)
+    }
+
+    /// Ranks the data from highest to lowest quality.
+    fn sort_by_quality<Q>(&mut self, quality: Q)
+    where
+        Q: IntoIterator<Item = f64>,
+    {
+        self.quality.clear();
+        self.quality.extend(quality);
+        rank_by_quality(&self.quality, &mut self.order);
+    }
+
+    /// Runs PROSAC on the data and reports the result along with the inliers of the best model.
+    fn report<E, Data, I>(
+        &mut self,
+        estimator: &E,
+        data: I,
+        by_quality: bool,
+    ) -> ConsensusReport<E::Model>
+    where
+        E: Estimator<Data>,
+        I: Iterator<Item = Data> + Clone,
+    {
+        let mut report = self.search(estimator, data.clone(), by_quality);
+        if let Some(model) = &report.model {
+            report.inliers = self.scorer.inliers(data.map(|data| model.residual(&data)));
+        }
+        report
+    }
+
+    /// Runs PROSAC on the data, which is ranked by `self.order` if `by_quality` is set and is otherwise sorted.
+    ///
+    /// The inliers are left for the caller to fill in.
+    fn search<E, Data, I>(
+        &mut self,
+        estimator: &E,
+        data: I,
+        by_quality: bool,
+    ) -> ConsensusReport<E::Model>
+    where
+        E: Estimator<Data>,
+        I: Iterator<Item = Data> + Clone,
+    {
+        let len = data.clone().count();
+        let m = E::MIN_SAMPLES;
+        self.rejected_samples = 0;
+        if len < m {
+            return ConsensusReport::too_few_data();
+        }
+        let Self {
+            scorer,
+            max_iterations,
+            confidence,
+            growth_samples,
+            random_support,
+            termination,
+            sprt,
+            rng,
+            rejected_samples,
+            order,
+            is_inlier,
+            ..
+        } = self;
+        let (max_iterations, confidence) = (*max_iterations, *confidence);
+        if by_quality {
+            assert_eq!(
+                order.len(),
+                len,
+                "there must be exactly one quality per datapoint"
+            );
+        }
+        let rank = |rank: usize| if by_quality { order[rank] } else { rank };
+        if let Some(sprt) = sprt {
+            sprt.reset();
+        }
+        termination.reset();
+
+        let mut growth = Growth::new(len, m, *growth_samples);
+        let mut n_star = len;
+        let mut k_star = max_iterations;
+        let mut best: Option<(E::Model, f64, usize)> = None;
+        let mut hypotheses = 0;
+        let mut rejected_hypotheses = 0;
+        let mut indices = SampleBuffer::new(m);
+        while growth.samples() < k_star && !termination.should_stop(growth.samples()) {
+            growth.draw(rng, n_star, &mut indices);
+            for ix in indices.iter_mut() {
+                *ix = rank(*ix);
+            }
+            indices.sort_unstable();
+            let sample = Select::new(data.clone(), &indices);
+            if !estimator.is_sample_valid(sample.clone()) {
+                *rejected_samples += 1;
+                continue;
+            }
+
+            for model in estimator.estimate(sample.clone()) {
+                hypotheses += 1;
+                if !estimator.is_model_valid(&model, sample.clone()) {
+                    rejected_hypotheses += 1;
+                    continue;
+                }
+                if let Some(sprt) = sprt {
+                    if let Verification::Rejected { .. } = sprt.verify(&model, data.clone()) {
+                        rejected_hypotheses += 1;
+                        continue;
+                    }
+                }
+                let score = scorer.score(data.clone().map(|data| model.residual(&data)));
+                if best.as_ref().is_none_or(|&(_, best, _)| score.value > best) {
+                    let inliers = score.inliers;
+                    is_inlier.clear();
+                    is_inlier.resize(len, false);
+                    for ix in scorer.inliers(data.clone().map(|data| model.residual(&data))) {
+                        is_inlier[ix] = true;
+                    }
+                    // Find the pool size that requires the fewest iterations while still being non-random.
+                    n_star = len;
+                    k_star = max_iterations;
+                    let mut pool_inliers = 0;
+                    for size in 1..=len {
+                        pool_inliers += is_inlier[rank(size - 1)] as usize;
+                        if size <= m || !is_non_random(pool_inliers, size, m, *random_support) {
+                            continue;
+                        }
+                        let k = prosac_required_iterations(
+                            confidence,
+                            pool_inliers,
+                            size,
+                            m,
+                            max_iterations,
+                        );
+                        if k < k_star {
+                            k_star = k;
+                            n_star = size;
+                        }
+                    }
+                    if let Some(sprt) = sprt {
+                        sprt.update_epsilon(inliers as f64 / len as f64);
+                    }
+                    termination.update(&BestHypothesis {
+                        score: score.value,
+                        inliers,
+                        len,
+                        sample_size: m,
+                        decision_threshold: sprt.as_ref().map(Sprt::decision_threshold),
+                    });
+                    best = Some((model, score.value, inliers));
+                }
+            }
+        }
+        let iterations = growth.samples();
+        let best_inliers = best.as_ref().map_or(0, |&(_, _, inliers)| inliers);
+        let (model, score) = best.map(|(model, score, _)| (model, score)).unzip();
+        ConsensusReport {
+            model,
+            inliers: Vec::new(),
+            score,
+            iterations,
+            hypotheses,
+            rejected_samples: *rejected_samples,
+            rejected_hypotheses,
+            // The termination criterion stopped the search if the PROSAC stopping criteria didn't.
+            termination: if iterations < k_star {
+                termination.reason()
+            } else if k_star < max_iterations {
+                TerminationReason::Converged
+            } else {
+                TerminationReason::MaxIterations
+            },
+            confidence: achieved_confidence(best_inliers as f64 / len as f64, m, iterations),
+        }
+    }
+}
+
+/// Fills `order` with the indices of the data from highest to lowest quality.
+fn rank_by_quality(quality: &[f64], order: &mut Vec<usize>) {
+    order.clear();
+    order.extend(0..quality.len());
+    order.sort_unstable_by(|&a, &b| quality[b].total_cmp(&quality[a]));
+}
+
+/// Checks if `inliers` out of the top `n` datapoints are unlikely to support a model by chance.
+///
+/// This uses the normal approximation of the binomial distribution of the number of outliers
+/// that support an incorrect model at the 5% significance level.
+fn is_non_random(inliers: usize, n: usize, m: usize, random_support: f64) -> bool {
+    let trials = (n - m) as f64;
+    let mean = trials * random_support;
+    let variance = trials * random_support * (1.0 - random_support);
+    inliers as f64 >= m as f64 + mean + 1.645 * libm::sqrt(variance)
+}
+
+impl<E, R, C, T, Data> Consensus<E, Data> for Prosac<R, C, T>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    C: Scorer,
+    T: Termination,
+{
+    type Inliers = Vec<usize>;
+
+    fn model<I>(&mut self, estimator: &E, data: I) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.search(estimator, data, false).model
+    }
+
+    fn model_inliers<I>(&mut self, estimator: &E, data: I) -> Option<(E::Model, Self::Inliers)>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let report = self.model_report(estimator, data);
+        Some((report.model?, report.inliers))
+    }
+}
+
+impl<E, R, C, T, Data> ReportingConsensus<E, Data> for Prosac<R, C, T>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    C: Scorer,
+    T: Termination,
+{
+    fn model_report<I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.report(estimator, data, false)
+    }
+}
+
+/// A [`Sampler`] which draws samples progressively from data ranked by quality, like [`Prosac`] does.
+///
+/// This brings the sampling of PROSAC to any consensus. Since the consensus decides when to stop rather than
+/// the PROSAC stopping criteria, the pool of top-ranked data keeps growing until it covers all of the data,
+/// after which samples are drawn uniformly at random.
+///
+/// By default, the data must be sorted from highest to lowest quality. Unsorted data can be ranked with
+/// [`ProsacSampler::quality`].
+#[derive(Clone, Debug)]
+pub struct ProsacSampler {
+    growth_samples: usize,
+    order: Vec<usize>,
+    growth: Option<Growth>,
+}
+
+impl ProsacSampler {
+    /// Creates a new `ProsacSampler` for data sorted from highest to lowest quality.
+    pub fn new() -> Self {
+        Self {
+            growth_samples: 200_000,
+            order: Vec::new(),
+            growth: None,
+        }
+    }
+
+    /// Ranks the data by one quality per datapoint, where a higher quality means a datapoint is more likely
+    /// to be an inlier. The data then doesn't need to be sorted, but the sampler panics if it isn't used on
+    /// exactly one datapoint per quality.
+    ///
+    /// Default: the data is sorted from highest to lowest quality
+    pub fn quality<Q>(mut self, quality: Q) -> Self
+    where
+        Q: IntoIterator<Item = f64>,
+    {
+        let quality: Vec<f64> = quality.into_iter().collect();
+        rank_by_quality(&quality, &mut self.order);
+        self
+    }
+
+    /// The number of samples after which samples are drawn from all of the data. See [`Prosac::growth_samples`].
+    ///
+    /// Default: `200_000`
+    pub fn growth_samples(self, growth_samples: usize) -> Self {
+        Self {
+            growth_samples,
+            ..self
+        }
+    }
+}
+
+impl Default for ProsacSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sampler for ProsacSampler {
+    fn reset(&mut self) {
+        self.growth = None;
+    }
+
+    fn sample<R>(&mut self, rng: &mut R, len: usize, sample: &mut [usize]) -> bool
+    where
+        R: RngCore,
+    {
+        let size = sample.len();
+        if len < size {
+            return false;
+        }
+        let growth_samples = self.growth_samples;
+        self.growth
+            .get_or_insert_with(|| Growth::new(len, size, growth_samples))
+            .draw(rng, len, sample);
+        if !self.order.is_empty() {
+            assert_eq!(
+                self.order.len(),
+                len,
+                "there must be exactly one quality per datapoint"
+            );
+            for ix in sample.iter_mut() {
+                *ix = self.order[*ix];
+            }
+        }
+        sample.sort_unstable();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{noisy_line, rng, LineEstimator, LINE};
+    use crate::MsacScorer;
+
+    #[test]
+    fn search_converges_on_a_line() {
+        let data = noisy_line(0, 60, 140);
+        let report =
+            Prosac::new(0.2, rng(0)).model_report(&LineEstimator::default(), data.iter().copied());
+        assert_eq!(report.termination, TerminationReason::Converged);
+        let model = report.model.unwrap();
+        assert!((model.slope - LINE.slope).abs() < 0.1);
+        assert!(report.inliers.len() >= 50);
+    }
+
+    #[test]
+    fn termination_stops_the_search() {
+        let data = noisy_line(0, 60, 140);
+        let report = Prosac::new(0.2, rng(0))
+            .termination(MaxIterations::new(5))
+            .model_report(&LineEstimator::default(), data.iter().copied());
+        assert_eq!(report.iterations, 5);
+        assert_eq!(report.termination, TerminationReason::MaxIterations);
+    }
+
+    #[test]
+    fn search_scores_with_the_scorer() {
+        let data = noisy_line(0, 60, 140);
+        let mut scorer = MsacScorer::new(0.2);
+        let report = Prosac::new(0.2, rng(0))
+            .scorer(scorer)
+            .model_report(&LineEstimator::default(), data.iter().copied());
+        let model = report.model.unwrap();
+        assert!((model.slope - LINE.slope).abs() < 0.1);
+        let residuals = data.iter().map(|data| model.residual(data));
+        assert_eq!(report.score, Some(scorer.score(residuals.clone()).value));
+        assert_eq!(report.inliers, scorer.inliers(residuals));
+    }
+}
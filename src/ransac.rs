@@ -0,0 +1,246 @@
+use crate::search::Search;
+use crate::{
+    AcceptAll, Adaptive, Consensus, ConsensusReport, CountScorer, Estimator, Model,
+    ReportingConsensus, Sampler, Scorer, Sprt, Termination, Uniform,
+};
+use alloc::vec::Vec;
+use rand_core::RngCore;
+
+/// The classic RANSAC algorithm.
+///
+/// Minimal samples are drawn by a [`Sampler`], uniformly at random by default, and the hypothesis with the most
+/// data points within the inlier threshold wins. The number of iterations adapts to the best inlier ratio seen so
+/// far so that an all-inlier sample is drawn with the configured confidence, but it never exceeds the maximum
+/// iterations. This can be replaced by any other [`Termination`] criterion.
+///
+/// The hypotheses can instead be scored by any [`Scorer`], such as the [`MsacScorer`](crate::MsacScorer), to
+/// compare scoring functions with everything else staying the same.
+///
+/// Searching for the model does not allocate, as long as the [`Sampler`] doesn't. Only the list of inliers returned
+/// by [`Consensus::model_inliers`] is allocated.
+#[derive(Clone, Debug)]
+pub struct Ransac<R, S = Uniform, C = CountScorer, T = Adaptive> {
+    scorer: C,
+    termination: T,
+    sprt: Option<Sprt>,
+    rng: R,
+    sampler: S,
+    rejected_samples: usize,
+}
+
+impl<R> Ransac<R>
+where
+    R: RngCore,
+{
+    /// Creates a new `Ransac` which considers a datapoint an inlier when its residual is below `threshold`.
+    ///
+    /// `rng` is used to draw the samples. By default, at most `1000` iterations are run and iteration stops
+    /// early once an all-inlier sample has been drawn with a confidence of `0.99`.
+    pub fn new(threshold: f64, rng: R) -> Self {
+        Self::with_scorer(CountScorer::new(threshold), rng)
+    }
+}
+
+impl<R, C> Ransac<R, Uniform, C>
+where
+    R: RngCore,
+{
+    /// Creates a new `Ransac` which scores the hypotheses with `scorer`, with the defaults of [`Ransac::new`].
+    pub(crate) fn with_scorer(scorer: C, rng: R) -> Self {
+        Self {
+            scorer,
+            termination: Adaptive::new(0.99, 1000),
+            sprt: None,
+            rng,
+            sampler: Uniform,
+            rejected_samples: 0,
+        }
+    }
+}
+
+impl<R, S, T> Ransac<R, S, CountScorer, T>
+where
+    R: RngCore,
+{
+    /// The residual below which a datapoint is considered an inlier.
+    ///
+    /// Default: specified in [`Ransac::new`]
+    pub fn threshold(self, threshold: f64) -> Self {
+        Self {
+            scorer: CountScorer::new(threshold),
+            ..self
+        }
+    }
+}
+
+impl<R, S, C> Ransac<R, S, C>
+where
+    R: RngCore,
+{
+    /// The maximum number of samples to draw.
+    ///
+    /// Default: `1000`
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            termination: self.termination.max_iterations(max_iterations),
+            ..self
+        }
+    }
+
+    /// The probability of having drawn at least one all-inlier sample at which iteration stops.
+    ///
+    /// Default: `0.99`
+    pub fn confidence(self, confidence: f64) -> Self {
+        Self {
+            termination: self.termination.confidence(confidence),
+            ..self
+        }
+    }
+}
+
+impl<R, S, C, T> Ransac<R, S, C, T>
+where
+    R: RngCore,
+{
+    /// Verifies hypotheses with the [`Sprt`] so that bad ones are rejected early.
+    ///
+    /// Default: every hypothesis is evaluated on all of the data
+    pub fn sprt(self, sprt: Sprt) -> Self {
+        Self {
+            sprt: Some(sprt),
+            ..self
+        }
+    }
+
+    /// The [`Sampler`] which draws the minimal samples.
+    ///
+    /// Default: [`Uniform`]
+    pub fn sampler<X>(self, sampler: X) -> Ransac<R, X, C, T> {
+        Ransac {
+            scorer: self.scorer,
+            termination: self.termination,
+            sprt: self.sprt,
+            rng: self.rng,
+            sampler,
+            rejected_samples: self.rejected_samples,
+        }
+    }
+
+    /// The [`Termination`] criterion which decides when to stop drawing samples, instead of the maximum number
+    /// of iterations and the confidence.
+    ///
+    /// Default: [`Adaptive`] with a confidence of `0.99` and at most `1000` iterations
+    pub fn termination<X>(self, termination: X) -> Ransac<R, S, C, X> {
+        Ransac {
+            scorer: self.scorer,
+            termination,
+            sprt: self.sprt,
+            rng: self.rng,
+            sampler: self.sampler,
+            rejected_samples: self.rejected_samples,
+        }
+    }
+
+    /// The [`Scorer`] which scores the hypotheses and decides which datapoints are inliers.
+    ///
+    /// Default: [`CountScorer`] with the threshold specified in [`Ransac::new`]
+    pub fn scorer<D>(self, scorer: D) -> Ransac<R, S, D, T> {
+        Ransac {
+            scorer,
+            termination: self.termination,
+            sprt: self.sprt,
+            rng: self.rng,
+            sampler: self.sampler,
+            rejected_samples: self.rejected_samples,
+        }
+    }
+
+    /// Replaces the scorer with one derived from the current scorer.
+    pub(crate) fn map_scorer(self, f: impl FnOnce(C) -> C) -> Self {
+        Self {
+            scorer: f(self.scorer),
+            ..self
+        }
+    }
+
+    /// The number of minimal samples rejected by [`Estimator::is_sample_valid`] during the last search.
+    pub fn rejected_samples(&self) -> usize {
+        self.rejected_samples
+    }
+}
+
+impl<R, S, C, T> Ransac<R, S, C, T>
+where
+    R: RngCore,
+    S: Sampler,
+    T: Termination,
+    C: Scorer,
+{
+    /// Runs the search and reports the best model, without its inliers.
+    fn search<E, Data, I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        E: Estimator<Data>,
+        I: Iterator<Item = Data> + Clone,
+    {
+        let scorer = &mut self.scorer;
+        Search {
+            rng: &mut self.rng,
+            sampler: &mut self.sampler,
+            sample_check: &mut AcceptAll,
+            model_check: &mut AcceptAll,
+            verifier: &mut self.sprt,
+            rejected_samples: &mut self.rejected_samples,
+            termination: &mut self.termination,
+        }
+        .run(estimator, data, |model, data| {
+            let score = scorer.score(data.map(|data| model.residual(&data)));
+            (score.value, score.inliers)
+        })
+    }
+}
+
+impl<E, R, S, C, T, Data> Consensus<E, Data> for Ransac<R, S, C, T>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    S: Sampler,
+    T: Termination,
+    C: Scorer,
+{
+    type Inliers = Vec<usize>;
+
+    fn model<I>(&mut self, estimator: &E, data: I) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.search(estimator, data).model
+    }
+
+    fn model_inliers<I>(&mut self, estimator: &E, data: I) -> Option<(E::Model, Self::Inliers)>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let report = self.model_report(estimator, data);
+        Some((report.model?, report.inliers))
+    }
+}
+
+impl<E, R, S, C, T, Data> ReportingConsensus<E, Data> for Ransac<R, S, C, T>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    S: Sampler,
+    T: Termination,
+    C: Scorer,
+{
+    fn model_report<I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let mut report = self.search(estimator, data.clone());
+        if let Some(model) = &report.model {
+            report.inliers = self.scorer.inliers(data.map(|data| model.residual(&data)));
+        }
+        report
+    }
+}
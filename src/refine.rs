@@ -0,0 +1,345 @@
+use crate::sample::{random_index, Select};
+use crate::search::{count_inliers, inliers};
+use crate::{Estimator, NonMinimalEstimator, Termination};
+use alloc::vec::Vec;
+use rand_core::RngCore;
+
+/// A `Refiner` improves a hypothesis using the data that supports it.
+///
+/// This is the local optimization stage of [`LoRansac`](crate::LoRansac). It is only run when the consensus
+/// finds a new best hypothesis, so it can afford to be far more expensive than drawing a sample.
+pub trait Refiner<E, Data>
+where
+    E: Estimator<Data>,
+{
+    /// Attempts to improve `model`, whose inliers are the datapoints with a residual below `threshold`.
+    ///
+    /// Returns `None` if no model could be produced. The returned model is only used by the consensus if
+    /// it is better than `model`, so it is fine to return a model which turns out to be worse.
+    ///
+    /// Refiners which take several steps should check [`Termination::is_interrupted`] on the `termination`
+    /// criterion of the consensus between them, and return the best model so far once it returns `true`.
+    fn refine<I, R, T>(
+        &mut self,
+        estimator: &E,
+        model: &E::Model,
+        data: I,
+        threshold: f64,
+        rng: &mut R,
+        termination: &mut T,
+    ) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+        R: RngCore,
+        T: Termination;
+}
+
+/// The local optimization from the LO-RANSAC paper by Chum et al.
+///
+/// An inner RANSAC draws non-minimal samples from the inliers of the hypothesis. Every model estimated from
+/// them, as well as the hypothesis itself, is then improved by iteratively re-estimating it from all of its
+/// inliers while shrinking the threshold from a multiple of the inlier threshold down to the inlier threshold.
+/// The model with the most inliers wins.
+///
+/// The models are re-estimated from more than [`Estimator::MIN_SAMPLES`] datapoints with
+/// [`NonMinimalEstimator::estimate_non_minimal`].
+#[derive(Clone, Debug)]
+pub struct InnerRansac {
+    inner_iterations: usize,
+    sample_size_multiplier: usize,
+    threshold_multiplier: f64,
+    shrink_steps: usize,
+    inliers: Vec<usize>,
+    sample: Vec<usize>,
+    subset: Vec<usize>,
+}
+
+impl InnerRansac {
+    /// Creates a new `InnerRansac` with the default settings from the LO-RANSAC paper.
+    pub fn new() -> Self {
+        Self {
+            inner_iterations: 10,
+            sample_size_multiplier: 7,
+            threshold_multiplier: 3.0,
+            shrink_steps: 4,
+            inliers: Vec::new(),
+            sample: Vec::new(),
+            subset: Vec::new(),
+        }
+    }
+
+    /// The number of non-minimal samples drawn from the inliers. If this is `0`, only the
+    /// hypothesis itself is iteratively re-estimated.
+    ///
+    /// Default: `10`
+    pub fn inner_iterations(self, inner_iterations: usize) -> Self {
+        Self {
+            inner_iterations,
+            ..self
+        }
+    }
+
+    /// The size of the non-minimal samples as a multiple of [`Estimator::MIN_SAMPLES`].
+    /// Samples are never larger than half of the inliers.
+    ///
+    /// Default: `7`
+    pub fn sample_size_multiplier(self, sample_size_multiplier: usize) -> Self {
+        Self {
+            sample_size_multiplier,
+            ..self
+        }
+    }
+
+    /// The multiple of the inlier threshold which the iterative re-estimation starts from.
+    ///
+    /// Default: `3.0`
+    pub fn threshold_multiplier(self, threshold_multiplier: f64) -> Self {
+        Self {
+            threshold_multiplier,
+            ..self
+        }
+    }
+
+    /// The number of steps taken to shrink the threshold down to the inlier threshold. If this is `0`, the model
+    /// is re-estimated once from its inliers at the inlier threshold.
+    ///
+    /// Default: `4`
+    pub fn shrink_steps(self, shrink_steps: usize) -> Self {
+        Self {
+            shrink_steps,
+            ..self
+        }
+    }
+
+    /// Iteratively re-estimates the model from all of its inliers while shrinking the threshold.
+    ///
+    /// Returns the last re-estimated model along with its number of inliers, if any.
+    fn shrink<E, Data, I, T>(
+        &mut self,
+        estimator: &E,
+        model: &E::Model,
+        data: I,
+        threshold: f64,
+        termination: &mut T,
+    ) -> Option<(E::Model, usize)>
+    where
+        E: NonMinimalEstimator<Data>,
+        I: Iterator<Item = Data> + Clone,
+        T: Termination,
+    {
+        let mut shrunk: Option<(E::Model, usize)> = None;
+        let start = if self.shrink_steps == 0 {
+            threshold
+        } else {
+            threshold * self.threshold_multiplier
+        };
+        let step = (start - threshold) / self.shrink_steps.max(1) as f64;
+        for step_ix in 0..=self.shrink_steps {
+            if termination.is_interrupted() {
+                break;
+            }
+            let current = shrunk.as_ref().map_or(model, |(model, _)| model);
+            let current_threshold = start - step * step_ix as f64;
+            self.subset.clear();
+            self.subset
+                .extend(inliers(current, data.clone(), current_threshold));
+            if self.subset.len() < E::MIN_SAMPLES {
+                break;
+            }
+            let estimated = estimator
+                .estimate_non_minimal(Select::new(data.clone(), &self.subset))
+                .map(|model| {
+                    let inliers = count_inliers(&model, data.clone(), threshold);
+                    (model, inliers)
+                });
+            if estimated.is_none() {
+                break;
+            }
+            shrunk = estimated;
+        }
+        shrunk
+    }
+}
+
+impl Default for InnerRansac {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E, Data> Refiner<E, Data> for InnerRansac
+where
+    E: NonMinimalEstimator<Data>,
+{
+    fn refine<I, R, T>(
+        &mut self,
+        estimator: &E,
+        model: &E::Model,
+        data: I,
+        threshold: f64,
+        rng: &mut R,
+        termination: &mut T,
+    ) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+        R: RngCore,
+        T: Termination,
+    {
+        let mut best = self.shrink(estimator, model, data.clone(), threshold, termination);
+
+        self.inliers.clear();
+        self.inliers.extend(inliers(model, data.clone(), threshold));
+        let sample_size = (E::MIN_SAMPLES * self.sample_size_multiplier)
+            .min(self.inliers.len() / 2)
+            .max(E::MIN_SAMPLES);
+        if self.inliers.len() <= sample_size {
+            return best.map(|(model, _)| model);
+        }
+
+        for _ in 0..self.inner_iterations {
+            if termination.is_interrupted() {
+                break;
+            }
+            self.sample.clear();
+            while self.sample.len() < sample_size {
+                let ix = self.inliers[random_index(rng, self.inliers.len())];
+                if !self.sample.contains(&ix) {
+                    self.sample.push(ix);
+                }
+            }
+            self.sample.sort_unstable();
+            let sample = core::mem::take(&mut self.sample);
+            if let Some(model) = estimator.estimate_non_minimal(Select::new(data.clone(), &sample))
+            {
+                if let Some(candidate) =
+                    self.shrink(estimator, &model, data.clone(), threshold, termination)
+                {
+                    if best.as_ref().is_none_or(|best| candidate.1 > best.1) {
+                        best = Some(candidate);
+                    }
+                }
+            }
+            self.sample = sample;
+        }
+        best.map(|(model, _)| model)
+    }
+}
+
+/// Re-estimates the model from all of its inliers until its number of inliers stops growing.
+///
+/// This is a common final refinement after consensus, such as in [`Usac`](crate::Usac). The model is re-estimated
+/// with [`NonMinimalEstimator::estimate_non_minimal`].
+#[derive(Clone, Debug)]
+pub struct Refit {
+    iterations: usize,
+    inliers: Vec<usize>,
+}
+
+impl Refit {
+    /// Creates a new `Refit` which re-estimates the model at most `10` times.
+    pub fn new() -> Self {
+        Self {
+            iterations: 10,
+            inliers: Vec::new(),
+        }
+    }
+
+    /// The maximum number of times the model is re-estimated.
+    ///
+    /// Default: `10`
+    pub fn iterations(self, iterations: usize) -> Self {
+        Self { iterations, ..self }
+    }
+}
+
+impl Default for Refit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E, Data> Refiner<E, Data> for Refit
+where
+    E: NonMinimalEstimator<Data>,
+{
+    fn refine<I, R, T>(
+        &mut self,
+        estimator: &E,
+        model: &E::Model,
+        data: I,
+        threshold: f64,
+        _rng: &mut R,
+        termination: &mut T,
+    ) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+        R: RngCore,
+        T: Termination,
+    {
+        let mut best: Option<(E::Model, usize)> = None;
+        for _ in 0..self.iterations {
+            if termination.is_interrupted() {
+                break;
+            }
+            let current = best.as_ref().map_or(model, |(model, _)| model);
+            self.inliers.clear();
+            self.inliers
+                .extend(inliers(current, data.clone(), threshold));
+            if self.inliers.len() < E::MIN_SAMPLES {
+                break;
+            }
+            let estimated = estimator
+                .estimate_non_minimal(Select::new(data.clone(), &self.inliers))
+                .map(|model| {
+                    let inliers = count_inliers(&model, data.clone(), threshold);
+                    (model, inliers)
+                });
+            match estimated {
+                Some(estimated) if best.as_ref().is_none_or(|best| estimated.1 > best.1) => {
+                    best = Some(estimated)
+                }
+                _ => break,
+            }
+        }
+        best.map(|(model, _)| model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{rng, LineEstimator, LINE};
+    use crate::MaxIterations;
+
+    /// Points on [`LINE`] along with as many points `0.5` above it.
+    fn offset_line() -> Vec<[f64; 2]> {
+        (0..20)
+            .map(|x| {
+                let x = f64::from(x) / 2.0;
+                let offset = if x.fract() == 0.0 { 0.0 } else { 0.5 };
+                [x, LINE.slope * x + LINE.intercept + offset]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn without_shrink_steps_the_model_is_refit_at_the_inlier_threshold() {
+        let estimator = LineEstimator::default();
+        // The points above the line are only inliers at the multiple of the threshold.
+        let model = InnerRansac::new()
+            .inner_iterations(0)
+            .shrink_steps(0)
+            .refine(
+                &estimator,
+                &LINE,
+                offset_line().into_iter(),
+                0.2,
+                &mut rng(0),
+                &mut MaxIterations::new(usize::MAX),
+            )
+            .unwrap();
+        assert!((model.slope - LINE.slope).abs() < 1e-9);
+        assert!((model.intercept - LINE.intercept).abs() < 1e-9);
+        assert_eq!(estimator.fits.get(), 1);
+    }
+}
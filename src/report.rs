@@ -0,0 +1,261 @@
+use crate::{Consensus, Estimator};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Why a consensus stopped searching.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TerminationReason {
+    /// There were fewer datapoints than [`Estimator::MIN_SAMPLES`], so no sample could be drawn.
+    TooFewData,
+    /// The stopping criterion was met, such as having drawn an all-inlier sample with the configured confidence.
+    Converged,
+    /// The maximum number of iterations ran out before the stopping criterion was met.
+    MaxIterations,
+    /// The time budget ran out before the stopping criterion was met.
+    TimeBudget,
+    /// The search was cancelled through a [`Cancellation`](crate::Cancellation), and the best model found so far
+    /// was kept.
+    Cancelled,
+}
+
+/// Why a consensus failed to find a model, as returned by [`ReportingConsensus::try_model_inliers`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConsensusError {
+    /// There were fewer datapoints than [`Estimator::MIN_SAMPLES`].
+    TooFewData,
+    /// No model was scored, because every sample was rejected by [`Estimator::is_sample_valid`], the estimator
+    /// failed on it, or every model estimated from it was rejected.
+    Degenerate,
+    /// Models were scored, but even the best one had fewer than the minimum number of inliers.
+    NoSupport,
+    /// The iteration or time budget ran out before a single sample was drawn.
+    BudgetExhausted,
+    /// The search was cancelled before a model with the minimum number of inliers was found.
+    Cancelled,
+}
+
+impl fmt::Display for ConsensusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::TooFewData => "too few datapoints to draw a sample",
+            Self::Degenerate => "every sample or model was rejected",
+            Self::NoSupport => "no model had enough inliers",
+            Self::BudgetExhausted => "ran out of budget before drawing a sample",
+            Self::Cancelled => "the search was cancelled",
+        })
+    }
+}
+
+/// The result of a consensus along with how it was reached, as returned by [`ReportingConsensus::model_report`].
+#[derive(Clone, Debug)]
+pub struct ConsensusReport<M> {
+    /// The best model, or `None` if no model was found.
+    pub model: Option<M>,
+    /// The indices of the inliers of the best model. This is empty if no model was found.
+    pub inliers: Vec<usize>,
+    /// The score of the best model, where higher is better. Scores are only comparable between runs of the same
+    /// consensus on the same data.
+    pub score: Option<f64>,
+    /// The number of samples drawn.
+    pub iterations: usize,
+    /// The number of models estimated from the samples.
+    pub hypotheses: usize,
+    /// The number of samples rejected by [`Estimator::is_sample_valid`] before a model was estimated from them.
+    pub rejected_samples: usize,
+    /// The number of models rejected before they were scored, such as by [`Estimator::is_model_valid`] or
+    /// the [`Sprt`](crate::Sprt).
+    pub rejected_hypotheses: usize,
+    /// Why the search stopped.
+    pub termination: TerminationReason,
+    /// The probability of having drawn at least one all-inlier sample, based on the inlier ratio of the best model
+    /// and the number of samples drawn.
+    pub confidence: f64,
+}
+
+impl<M> ConsensusReport<M> {
+    /// The report of a search which didn't draw a single sample because there were too few datapoints.
+    pub(crate) fn too_few_data() -> Self {
+        Self {
+            model: None,
+            inliers: Vec::new(),
+            score: None,
+            iterations: 0,
+            hypotheses: 0,
+            rejected_samples: 0,
+            rejected_hypotheses: 0,
+            termination: TerminationReason::TooFewData,
+            confidence: 0.0,
+        }
+    }
+
+    /// Returns the best model and its inliers if it has at least `min_inliers` inliers, and otherwise why no such
+    /// model was found.
+    ///
+    /// A model with enough inliers is returned even if the search ran out of iterations before reaching its
+    /// confidence. Check [`ConsensusReport::termination`] to tell these apart. Otherwise the error depends on how
+    /// far the search got: a cancelled search is always reported as cancelled, a search which didn't score a
+    /// single model is degenerate, or out of budget if it didn't even draw a sample, and a search which scored
+    /// models found no model with enough support.
+    pub fn into_result(self, min_inliers: usize) -> Result<(M, Vec<usize>), ConsensusError> {
+        if let Some(model) = self.model {
+            if self.inliers.len() >= min_inliers {
+                return Ok((model, self.inliers));
+            }
+        }
+        Err(match self.termination {
+            TerminationReason::TooFewData => ConsensusError::TooFewData,
+            TerminationReason::Cancelled => ConsensusError::Cancelled,
+            _ if self.hypotheses.saturating_sub(self.rejected_hypotheses) > 0 => {
+                ConsensusError::NoSupport
+            }
+            _ if self.iterations == 0 => ConsensusError::BudgetExhausted,
+            _ => ConsensusError::Degenerate,
+        })
+    }
+}
+
+/// A [`Consensus`] which can report how it reached its result, so that failures can be told apart, such as running
+/// out of iterations, every sample being degenerate, or the estimator never producing a model.
+pub trait ReportingConsensus<E, Data>: Consensus<E, Data>
+where
+    E: Estimator<Data>,
+{
+    /// Takes an iterator over the data and an estimator instance.
+    /// It returns a report of the best model, its inliers, and statistics about the search.
+    ///
+    /// Make sure to shuffle your `data` before calling this. You can use
+    /// [`SliceRandom::shuffle`](https://docs.rs/rand/0.8.4/rand/seq/trait.SliceRandom.html#tymethod.shuffle).
+    fn model_report<I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone;
+
+    /// Takes an iterator over the data and an estimator instance.
+    /// It returns the best model and its inliers if it has at least `min_inliers` inliers, and otherwise a
+    /// [`ConsensusError`] saying why no such model was found.
+    ///
+    /// Make sure to shuffle your `data` before calling this. You can use
+    /// [`SliceRandom::shuffle`](https://docs.rs/rand/0.8.4/rand/seq/trait.SliceRandom.html#tymethod.shuffle).
+    fn try_model_inliers<I>(
+        &mut self,
+        estimator: &E,
+        data: I,
+        min_inliers: usize,
+    ) -> Result<(E::Model, Vec<usize>), ConsensusError>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.model_report(estimator, data).into_result(min_inliers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    /// The report of a search which drew `iterations` samples and estimated `hypotheses` models from them, of
+    /// which it rejected `rejected_hypotheses`.
+    fn report(
+        termination: TerminationReason,
+        iterations: usize,
+        hypotheses: usize,
+        rejected_hypotheses: usize,
+    ) -> ConsensusReport<()> {
+        ConsensusReport {
+            iterations,
+            hypotheses,
+            rejected_hypotheses,
+            termination,
+            ..ConsensusReport::too_few_data()
+        }
+    }
+
+    /// The same as [`report`], but the best model has `inliers` inliers.
+    fn supported(termination: TerminationReason, inliers: usize) -> ConsensusReport<()> {
+        ConsensusReport {
+            model: Some(()),
+            inliers: (0..inliers).collect(),
+            score: Some(inliers as f64),
+            ..report(termination, 10, 10, 5)
+        }
+    }
+
+    #[test]
+    fn into_result_returns_a_supported_model() {
+        for termination in [
+            TerminationReason::Converged,
+            TerminationReason::MaxIterations,
+            TerminationReason::Cancelled,
+        ] {
+            assert_eq!(
+                supported(termination, 3).into_result(3),
+                Ok(((), vec![0, 1, 2]))
+            );
+        }
+    }
+
+    #[test]
+    fn into_result_with_too_few_data() {
+        assert_eq!(
+            ConsensusReport::<()>::too_few_data().into_result(0),
+            Err(ConsensusError::TooFewData)
+        );
+    }
+
+    #[test]
+    fn into_result_without_scored_models_is_degenerate() {
+        for termination in [
+            TerminationReason::Converged,
+            TerminationReason::MaxIterations,
+            TerminationReason::TimeBudget,
+        ] {
+            assert_eq!(
+                report(termination, 10, 0, 0).into_result(0),
+                Err(ConsensusError::Degenerate)
+            );
+            assert_eq!(
+                report(termination, 10, 7, 7).into_result(0),
+                Err(ConsensusError::Degenerate)
+            );
+        }
+    }
+
+    #[test]
+    fn into_result_with_too_few_inliers_is_no_support() {
+        for termination in [
+            TerminationReason::Converged,
+            TerminationReason::MaxIterations,
+            TerminationReason::TimeBudget,
+        ] {
+            assert_eq!(
+                supported(termination, 2).into_result(3),
+                Err(ConsensusError::NoSupport)
+            );
+        }
+    }
+
+    #[test]
+    fn into_result_without_samples_is_budget_exhausted() {
+        for termination in [
+            TerminationReason::MaxIterations,
+            TerminationReason::TimeBudget,
+        ] {
+            assert_eq!(
+                report(termination, 0, 0, 0).into_result(0),
+                Err(ConsensusError::BudgetExhausted)
+            );
+        }
+    }
+
+    #[test]
+    fn into_result_after_cancellation_is_cancelled() {
+        assert_eq!(
+            report(TerminationReason::Cancelled, 0, 0, 0).into_result(0),
+            Err(ConsensusError::Cancelled)
+        );
+        assert_eq!(
+            supported(TerminationReason::Cancelled, 2).into_result(3),
+            Err(ConsensusError::Cancelled)
+        );
+    }
+}
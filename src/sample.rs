@@ -0,0 +1,287 @@
+use crate::MAX_SAMPLE_SIZE;
+use core::ops::{Deref, DerefMut};
+use rand_core::RngCore;
+
+/// A uniformly random subset of `needed` items drawn from an iterator of known length.
+///
+/// This uses selection sampling (Knuth's Algorithm S), which only needs a single pass over the data and no
+/// memory to remember which items were picked. The sample is driven by a small internal generator seeded from
+/// the caller's RNG so that cloning the iterator reproduces exactly the same sample, as required by
+/// [`Estimator::estimate`](crate::Estimator::estimate).
+#[derive(Clone)]
+pub(crate) struct Sample<I> {
+    data: I,
+    state: u64,
+    remaining: usize,
+    needed: usize,
+}
+
+impl<I> Sample<I> {
+    /// Creates a sample of `needed` items out of the `len` items in `data`.
+    pub(crate) fn new(data: I, len: usize, needed: usize, seed: u64) -> Self {
+        Self {
+            data,
+            state: seed,
+            remaining: len,
+            needed,
+        }
+    }
+
+    /// SplitMix64, which is more than good enough to pick among the remaining items.
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl<I> Iterator for Sample<I>
+where
+    I: Iterator,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.needed != 0 {
+            let item = self.data.next()?;
+            // Pick this item with probability `needed / remaining`.
+            let roll = ((self.next_u64() as u128 * self.remaining as u128) >> 64) as usize;
+            self.remaining -= 1;
+            if roll < self.needed {
+                self.needed -= 1;
+                return Some(item);
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.needed, Some(self.needed))
+    }
+}
+
+/// The indices of a minimal sample, stored in a fixed-size buffer so that drawing samples never allocates.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct SampleBuffer {
+    indices: [usize; MAX_SAMPLE_SIZE],
+    len: usize,
+}
+
+impl SampleBuffer {
+    /// Creates a buffer for samples of `len` indices.
+    ///
+    /// Panics if `len` is more than [`MAX_SAMPLE_SIZE`].
+    pub(crate) fn new(len: usize) -> Self {
+        assert!(
+            len <= MAX_SAMPLE_SIZE,
+            "minimal samples of more than {} datapoints are not supported",
+            MAX_SAMPLE_SIZE
+        );
+        Self {
+            indices: [0; MAX_SAMPLE_SIZE],
+            len,
+        }
+    }
+}
+
+impl Deref for SampleBuffer {
+    type Target = [usize];
+
+    fn deref(&self) -> &[usize] {
+        &self.indices[..self.len]
+    }
+}
+
+impl DerefMut for SampleBuffer {
+    fn deref_mut(&mut self) -> &mut [usize] {
+        &mut self.indices[..self.len]
+    }
+}
+
+/// Draws an index uniformly at random from `0..len`.
+pub(crate) fn random_index<R>(rng: &mut R, len: usize) -> usize
+where
+    R: RngCore,
+{
+    ((rng.next_u64() as u128 * len as u128) >> 64) as usize
+}
+
+/// Iterates over the items of `data` at `indices`, which must be sorted in increasing order.
+#[derive(Clone)]
+pub(crate) struct Select<'a, I> {
+    data: I,
+    indices: &'a [usize],
+    position: usize,
+}
+
+impl<'a, I> Select<'a, I> {
+    pub(crate) fn new(data: I, indices: &'a [usize]) -> Self {
+        Self {
+            data,
+            indices,
+            position: 0,
+        }
+    }
+}
+
+impl<I> Iterator for Select<'_, I>
+where
+    I: Iterator,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&index, rest) = self.indices.split_first()?;
+        self.indices = rest;
+        let item = self.data.nth(index - self.position)?;
+        self.position = index + 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.indices.len()))
+    }
+}
+
+/// Draws a number uniformly at random from `[0, 1)`.
+pub(crate) fn random_unit<R>(rng: &mut R) -> f64
+where
+    R: RngCore,
+{
+    (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// The progressive growth of the pool of top-ranked datapoints that PROSAC draws samples from.
+///
+/// The expected number of samples drawn only from the top `n` datapoints out of `growth_samples` samples
+/// is `t_n`, while `t_n_prime` is the number of samples after which the pool grows to `n + 1`.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Growth {
+    size: usize,
+    t_n: f64,
+    t_n_prime: usize,
+    n: usize,
+    t: usize,
+}
+
+impl Growth {
+    /// Starts growing a pool of samples of `size` ranks out of `len` ranks, which must be at least `size`.
+    pub(crate) fn new(len: usize, size: usize, growth_samples: usize) -> Self {
+        Self {
+            size,
+            t_n: (0..size).fold(growth_samples as f64, |t_n, i| {
+                t_n * (size - i) as f64 / (len - i) as f64
+            }),
+            t_n_prime: 1,
+            n: size,
+            t: 0,
+        }
+    }
+
+    /// The number of samples drawn so far.
+    pub(crate) fn samples(&self) -> usize {
+        self.t
+    }
+
+    /// Whether the pool has grown to `limit` ranks, so that samples are drawn uniformly from them.
+    pub(crate) fn is_complete(&self, limit: usize) -> bool {
+        self.n >= limit && self.t >= self.t_n_prime
+    }
+
+    /// Draws the ranks of the next sample into `sample`, which must hold exactly `size` ranks, in no particular
+    /// order. The pool never grows beyond the top `limit` ranks.
+    pub(crate) fn draw<R>(&mut self, rng: &mut R, limit: usize, sample: &mut [usize])
+    where
+        R: RngCore,
+    {
+        let m = self.size;
+        debug_assert_eq!(sample.len(), m);
+        self.t += 1;
+        while self.t > self.t_n_prime && self.n < limit {
+            let t_next = self.t_n * (self.n + 1) as f64 / (self.n + 1 - m) as f64;
+            self.t_n_prime += libm::ceil(t_next - self.t_n) as usize;
+            self.t_n = t_next;
+            self.n += 1;
+        }
+
+        // Until the pool is exhausted, the lowest-ranked datapoint of the pool is always part of the sample.
+        let mut drawn = 0;
+        let pool = if self.t_n_prime < self.t {
+            self.n
+        } else {
+            sample[0] = self.n - 1;
+            drawn = 1;
+            self.n - 1
+        };
+        while drawn < m {
+            let ix = random_index(rng, pool);
+            if !sample[..drawn].contains(&ix) {
+                sample[drawn] = ix;
+                drawn += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::rng;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    /// `T_n` from the PROSAC paper, the expected number of the `growth_samples` samples drawn from all of the data
+    /// which only contain the top `n` ranks, which is `growth_samples * C(n, m) / C(len, m)`.
+    fn t_n(n: usize, len: usize, m: usize, growth_samples: usize) -> f64 {
+        (0..m).fold(growth_samples as f64, |t_n, i| {
+            t_n * (n - i) as f64 / (len - i) as f64
+        })
+    }
+
+    #[test]
+    fn growth_matches_the_prosac_growth_function() {
+        let (len, m, growth_samples) = (20, 2, 1001);
+        // T'_m = 1 and T'_(n + 1) = T'_n + ceil(T_(n + 1) - T_n).
+        let mut expected = vec![(m, 1)];
+        for n in m + 1..=len {
+            let t_n_prime = expected.last().unwrap().1
+                + libm::ceil(t_n(n, len, m, growth_samples) - t_n(n - 1, len, m, growth_samples))
+                    as usize;
+            expected.push((n, t_n_prime));
+        }
+
+        let mut growth = Growth::new(len, m, growth_samples);
+        let mut rng = rng(0);
+        let mut sample = [0; 2];
+        let mut pools = Vec::new();
+        while !growth.is_complete(len) {
+            growth.draw(&mut rng, len, &mut sample);
+            assert!(sample.iter().all(|&ix| ix < growth.n));
+            assert_ne!(sample[0], sample[1]);
+            // The lowest-ranked datapoint of the pool is part of every sample until the pool grows.
+            assert!(growth.samples() > growth.t_n_prime || sample.contains(&(growth.n - 1)));
+            if pools.last() != Some(&(growth.n, growth.t_n_prime)) {
+                pools.push((growth.n, growth.t_n_prime));
+            }
+        }
+        assert_eq!(pools, expected);
+        assert!(pools.windows(2).all(|pools| pools[0].1 < pools[1].1));
+        // The pool covers all of the data after about `growth_samples` samples.
+        assert!(growth.samples().abs_diff(growth_samples) < len);
+    }
+
+    #[test]
+    fn growth_stops_at_the_limit() {
+        let mut growth = Growth::new(20, 2, 1001);
+        let mut rng = rng(0);
+        let mut sample = [0; 2];
+        for _ in 0..2000 {
+            growth.draw(&mut rng, 5, &mut sample);
+            assert!(sample.iter().all(|&ix| ix < 5));
+        }
+        assert!(growth.is_complete(5));
+        assert!(!growth.is_complete(20));
+    }
+}
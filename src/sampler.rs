@@ -0,0 +1,298 @@
+use crate::sample::{random_index, random_unit, Growth, Sample};
+use crate::Neighborhood;
+use alloc::vec::Vec;
+use rand_core::RngCore;
+
+/// The largest minimal sample the consensus implementations in this crate can draw, which bounds
+/// [`Estimator::MIN_SAMPLES`](crate::Estimator::MIN_SAMPLES).
+///
+/// Samples are drawn into a buffer of this size so that searching for a model doesn't need to allocate.
+pub const MAX_SAMPLE_SIZE: usize = 32;
+
+/// A `Sampler` decides which datapoints are drawn together in the minimal samples that hypotheses are
+/// estimated from.
+///
+/// The consensus implementations in this crate which draw minimal samples at random accept any `Sampler`,
+/// so the sampling strategy can be chosen independently of how hypotheses are scored.
+pub trait Sampler {
+    /// Called at the start of every search, so that samplers which change over the course of a search
+    /// start over.
+    fn reset(&mut self) {}
+
+    /// Draws a sample of `sample.len()` distinct indices from `0..len` into `sample`, which is never longer than
+    /// [`MAX_SAMPLE_SIZE`]. The indices must be in increasing order.
+    ///
+    /// Returns `false` if no sample could be drawn, in which case the iteration is skipped.
+    fn sample<R>(&mut self, rng: &mut R, len: usize, sample: &mut [usize]) -> bool
+    where
+        R: RngCore;
+}
+
+/// Draws every sample uniformly at random, as in the original RANSAC.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Uniform;
+
+impl Sampler for Uniform {
+    fn sample<R>(&mut self, rng: &mut R, len: usize, sample: &mut [usize]) -> bool
+    where
+        R: RngCore,
+    {
+        let size = sample.len();
+        if len < size {
+            return false;
+        }
+        for (slot, ix) in sample
+            .iter_mut()
+            .zip(Sample::new(0..len, len, size, rng.next_u64()))
+        {
+            *slot = ix;
+        }
+        true
+    }
+}
+
+/// Guided sampling from the paper "Guided-MLESAC: Faster Image Transform Estimation by Using Matching Priors"
+/// by Tordoff and Murray.
+///
+/// Every datapoint is drawn with a probability proportional to its weight, such as a prior probability of being
+/// an inlier derived from descriptor distances. Likely inliers then end up together in samples far more often.
+#[derive(Clone, Debug)]
+pub struct Guided {
+    /// The sum of the weights up to and including every datapoint.
+    cumulative: Vec<f64>,
+    /// The number of datapoints with a positive weight.
+    positive: usize,
+}
+
+impl Guided {
+    /// Creates a new `Guided` sampler from one weight per datapoint. Datapoints with a weight of `0`
+    /// are never drawn.
+    ///
+    /// The sampler panics if it isn't used on exactly one datapoint per weight.
+    pub fn new<W>(weights: W) -> Self
+    where
+        W: IntoIterator<Item = f64>,
+    {
+        let mut total = 0.0;
+        let mut positive = 0;
+        let cumulative = weights
+            .into_iter()
+            .map(|weight| {
+                if weight > 0.0 {
+                    total += weight;
+                    positive += 1;
+                }
+                total
+            })
+            .collect();
+        Self {
+            cumulative,
+            positive,
+        }
+    }
+}
+
+impl Sampler for Guided {
+    fn sample<R>(&mut self, rng: &mut R, len: usize, sample: &mut [usize]) -> bool
+    where
+        R: RngCore,
+    {
+        assert_eq!(
+            self.cumulative.len(),
+            len,
+            "there must be exactly one weight per datapoint"
+        );
+        let size = sample.len();
+        if self.positive < size {
+            return false;
+        }
+        let mut drawn = 0;
+        let total = self.cumulative.last().copied().unwrap_or(0.0);
+        // Draws of datapoints already in the sample are rejected, which can take a while with a few
+        // overwhelming weights, so give up eventually.
+        for _ in 0..100 * size {
+            let roll = random_unit(rng) * total;
+            let ix = self
+                .cumulative
+                .partition_point(|&cumulative| cumulative <= roll)
+                .min(len - 1);
+            if !sample[..drawn].contains(&ix) {
+                sample[drawn] = ix;
+                drawn += 1;
+                if drawn == size {
+                    sample.sort_unstable();
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// NAPSAC from the paper "NAPSAC: High Noise, High Dimensional Robust Estimation - it's in the Bag"
+/// by Myatt et al.
+///
+/// A seed datapoint is drawn uniformly at random and the rest of the sample is drawn uniformly from its
+/// neighbors. Inliers of the same structure tend to be close to each other, so in scenes with several structures
+/// or a low inlier ratio, this draws all-inlier samples far more often than uniform sampling.
+///
+/// Seeds with too few neighbors to complete a sample are redrawn, up to a maximum number of attempts.
+#[derive(Clone, Debug)]
+pub struct Napsac<N> {
+    neighborhood: N,
+    max_attempts: usize,
+}
+
+impl<N> Napsac<N>
+where
+    N: Neighborhood,
+{
+    /// Creates a new `Napsac` which draws samples from the neighbors of a seed in `neighborhood`,
+    /// such as [`Neighbors`](crate::Neighbors).
+    pub fn new(neighborhood: N) -> Self {
+        Self {
+            neighborhood,
+            max_attempts: 100,
+        }
+    }
+
+    /// The maximum number of seeds to draw before giving up on a sample.
+    ///
+    /// Default: `100`
+    pub fn max_attempts(self, max_attempts: usize) -> Self {
+        Self {
+            max_attempts,
+            ..self
+        }
+    }
+}
+
+impl<N> Sampler for Napsac<N>
+where
+    N: Neighborhood,
+{
+    fn sample<R>(&mut self, rng: &mut R, len: usize, sample: &mut [usize]) -> bool
+    where
+        R: RngCore,
+    {
+        let size = sample.len();
+        if len < size || size == 0 {
+            return false;
+        }
+        for _ in 0..self.max_attempts {
+            let seed = random_index(rng, len);
+            let neighbors = self.neighborhood.neighbors(seed);
+            if neighbors.len() < size - 1 {
+                continue;
+            }
+            sample[0] = seed;
+            let drawn = Sample::new(
+                neighbors.iter().copied(),
+                neighbors.len(),
+                size - 1,
+                rng.next_u64(),
+            );
+            for (slot, ix) in sample[1..].iter_mut().zip(drawn) {
+                *slot = ix;
+            }
+            sample.sort_unstable();
+            // Guard against neighborhoods which list the seed, duplicates, or datapoints which don't exist.
+            if sample.windows(2).all(|pair| pair[0] < pair[1]) && sample[size - 1] < len {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Progressive NAPSAC from the paper "MAGSAC++, a fast, reliable and accurate robust estimator" by Barath et al.
+///
+/// Like [`Napsac`], a seed datapoint is drawn uniformly at random and the rest of the sample is drawn from its
+/// neighbors. However, the neighbors are drawn progressively, as in [`ProsacSampler`](crate::ProsacSampler):
+/// the first samples of a seed come from its nearest neighbors and the pool grows every time the seed is drawn
+/// again. Once the pool covers all of the neighbors of a seed, samples with that seed are drawn from all of the
+/// data. This combines the strength of NAPSAC on local structures with the global sampling of RANSAC.
+///
+/// The neighbors of every datapoint must be ordered from nearest to farthest, such as by [`Neighbors::nearest`].
+/// Seeds with too few neighbors to complete a sample are drawn with a uniform sample.
+///
+/// [`Neighbors::nearest`]: crate::Neighbors::nearest
+#[derive(Clone, Debug)]
+pub struct ProgressiveNapsac<N> {
+    neighborhood: N,
+    growth_samples: usize,
+    growth: Vec<Option<Growth>>,
+}
+
+impl<N> ProgressiveNapsac<N>
+where
+    N: Neighborhood,
+{
+    /// Creates a new `ProgressiveNapsac` which draws samples from the neighbors of a seed in `neighborhood`.
+    pub fn new(neighborhood: N) -> Self {
+        Self {
+            neighborhood,
+            growth_samples: 50,
+            growth: Vec::new(),
+        }
+    }
+
+    /// The number of times a seed is drawn after which its samples are drawn from all of its neighbors.
+    ///
+    /// This controls how fast the neighborhood of every seed grows, like [`Prosac::growth_samples`] does for
+    /// the whole data.
+    ///
+    /// Default: `50`
+    ///
+    /// [`Prosac::growth_samples`]: crate::Prosac::growth_samples
+    pub fn growth_samples(self, growth_samples: usize) -> Self {
+        Self {
+            growth_samples,
+            ..self
+        }
+    }
+}
+
+impl<N> Sampler for ProgressiveNapsac<N>
+where
+    N: Neighborhood,
+{
+    fn reset(&mut self) {
+        self.growth.clear();
+    }
+
+    fn sample<R>(&mut self, rng: &mut R, len: usize, sample: &mut [usize]) -> bool
+    where
+        R: RngCore,
+    {
+        let size = sample.len();
+        if len < size || size <= 1 {
+            return Uniform.sample(rng, len, sample);
+        }
+        let seed = random_index(rng, len);
+        let neighbors = self.neighborhood.neighbors(seed);
+        if neighbors.len() < size - 1 {
+            return Uniform.sample(rng, len, sample);
+        }
+        self.growth.resize(len, None);
+        let growth_samples = self.growth_samples;
+        let growth = self.growth[seed]
+            .get_or_insert_with(|| Growth::new(neighbors.len(), size - 1, growth_samples));
+        if growth.is_complete(neighbors.len()) {
+            return Uniform.sample(rng, len, sample);
+        }
+        let (last, drawn) = sample.split_last_mut().expect("the sample has a seed");
+        growth.draw(rng, neighbors.len(), drawn);
+        for ix in drawn.iter_mut() {
+            *ix = neighbors[*ix];
+        }
+        *last = seed;
+        sample.sort_unstable();
+        // Guard against neighborhoods which list the seed, duplicates, or datapoints which don't exist.
+        if sample.windows(2).all(|pair| pair[0] < pair[1]) && sample[size - 1] < len {
+            true
+        } else {
+            Uniform.sample(rng, len, sample)
+        }
+    }
+}
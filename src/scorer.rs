@@ -0,0 +1,66 @@
+use alloc::vec::Vec;
+
+/// The quality of a model according to a [`Scorer`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Score {
+    /// Higher is better. Values are only comparable between scores of the same scorer on the same data.
+    pub value: f64,
+    /// The number of inliers of the model, which drives the adaptive number of iterations.
+    pub inliers: usize,
+}
+
+/// A `Scorer` decides how good a model is from the residuals of the data, and which of the data are its inliers.
+///
+/// [`Ransac`](crate::Ransac) accepts any `Scorer`, so that different scoring functions can be compared with
+/// everything else staying the same.
+pub trait Scorer {
+    /// Scores a model from the residual of every datapoint, in order.
+    fn score<I>(&mut self, residuals: I) -> Score
+    where
+        I: Iterator<Item = f64>;
+
+    /// Returns the indices of the inliers of a model from the residual of every datapoint, in order.
+    fn inliers<I>(&mut self, residuals: I) -> Vec<usize>
+    where
+        I: Iterator<Item = f64>;
+}
+
+/// Scores a model by its number of inliers, which are the datapoints with a residual below a threshold,
+/// as in the original RANSAC.
+#[derive(Copy, Clone, Debug)]
+pub struct CountScorer {
+    threshold: f64,
+}
+
+impl CountScorer {
+    /// Creates a new `CountScorer` which considers a datapoint an inlier when its residual is below `threshold`.
+    pub fn new(threshold: f64) -> Self {
+        Self { threshold }
+    }
+}
+
+impl Scorer for CountScorer {
+    fn score<I>(&mut self, residuals: I) -> Score
+    where
+        I: Iterator<Item = f64>,
+    {
+        let inliers = residuals
+            .filter(|&residual| residual < self.threshold)
+            .count();
+        Score {
+            value: inliers as f64,
+            inliers,
+        }
+    }
+
+    fn inliers<I>(&mut self, residuals: I) -> Vec<usize>
+    where
+        I: Iterator<Item = f64>,
+    {
+        residuals
+            .enumerate()
+            .filter(|&(_, residual)| residual < self.threshold)
+            .map(|(ix, _)| ix)
+            .collect()
+    }
+}
@@ -0,0 +1,210 @@
+use crate::sample::{SampleBuffer, Select};
+use crate::termination::{achieved_confidence, BestHypothesis};
+use crate::{
+    ConsensusReport, Estimator, Model, ModelCheck, SampleCheck, Sampler, Termination, Verifier,
+};
+use alloc::vec::Vec;
+use rand_core::RngCore;
+
+/// The hypothesize-and-verify loop shared by the consensus implementations in this crate.
+///
+/// Minimal samples are drawn by the `sampler`, those rejected by [`Estimator::is_sample_valid`] or the
+/// `sample_check` are counted in `rejected_samples`, and every model estimated from them which passes
+/// [`Estimator::is_model_valid`] and the `model_check` is first verified by the `verifier` and then scored with
+/// `evaluate`, which returns the score (higher is better) and the number of inliers of the model. Iteration stops
+/// once the `termination` criterion is met.
+///
+/// The consensus implementations which only take an optional [`Sprt`](crate::Sprt) pass
+/// [`AcceptAll`](crate::AcceptAll) for both checks and the `Option<Sprt>` as the verifier.
+pub(crate) struct Search<'a, R, S, K, M, V, T> {
+    pub(crate) rng: &'a mut R,
+    pub(crate) sampler: &'a mut S,
+    pub(crate) sample_check: &'a mut K,
+    pub(crate) model_check: &'a mut M,
+    pub(crate) verifier: &'a mut V,
+    /// Reset at the start of the search.
+    pub(crate) rejected_samples: &'a mut usize,
+    pub(crate) termination: &'a mut T,
+}
+
+impl<R, S, K, M, V, T> Search<'_, R, S, K, M, V, T>
+where
+    R: RngCore,
+    S: Sampler,
+    T: Termination,
+{
+    /// Runs the search and reports the best hypothesis. The inliers are left for the consensus to fill in.
+    pub(crate) fn run<E, Data, I, F>(
+        self,
+        estimator: &E,
+        data: I,
+        evaluate: F,
+    ) -> ConsensusReport<E::Model>
+    where
+        E: Estimator<Data>,
+        K: SampleCheck<Data>,
+        M: ModelCheck<E::Model, Data>,
+        V: Verifier<Data>,
+        I: Iterator<Item = Data> + Clone,
+        F: FnMut(&E::Model, I) -> (f64, usize),
+    {
+        self.run_optimized(estimator, data, evaluate, |_, _, _, _, _| None)
+    }
+
+    /// The same as [`Search::run`], but every time a sample produces a new best hypothesis, `optimize` is given
+    /// a chance to improve on it, along with the sample it was estimated from and the `termination` criterion to
+    /// check for interruptions. The optimized model replaces
+    /// the best hypothesis if it passes [`Estimator::is_model_valid`] for that sample and scores higher.
+    pub(crate) fn run_optimized<E, Data, I, F, O>(
+        self,
+        estimator: &E,
+        data: I,
+        evaluate: F,
+        optimize: O,
+    ) -> ConsensusReport<E::Model>
+    where
+        E: Estimator<Data>,
+        K: SampleCheck<Data>,
+        M: ModelCheck<E::Model, Data>,
+        V: Verifier<Data>,
+        I: Iterator<Item = Data> + Clone,
+        F: FnMut(&E::Model, I) -> (f64, usize),
+        O: FnMut(&mut R, &mut T, &E::Model, Select<'_, I>, I) -> Option<E::Model>,
+    {
+        self.run_with_sample(estimator, data, evaluate, optimize).0
+    }
+
+    /// The same as [`Search::run_optimized`], but also returns the indices of the sample the best hypothesis was
+    /// estimated from.
+    pub(crate) fn run_with_sample<E, Data, I, F, O>(
+        self,
+        estimator: &E,
+        data: I,
+        mut evaluate: F,
+        mut optimize: O,
+    ) -> (ConsensusReport<E::Model>, SampleBuffer)
+    where
+        E: Estimator<Data>,
+        K: SampleCheck<Data>,
+        M: ModelCheck<E::Model, Data>,
+        V: Verifier<Data>,
+        I: Iterator<Item = Data> + Clone,
+        F: FnMut(&E::Model, I) -> (f64, usize),
+        O: FnMut(&mut R, &mut T, &E::Model, Select<'_, I>, I) -> Option<E::Model>,
+    {
+        let Self {
+            rng,
+            sampler,
+            sample_check,
+            model_check,
+            verifier,
+            rejected_samples,
+            termination,
+        } = self;
+        let mut indices = SampleBuffer::new(E::MIN_SAMPLES);
+        let mut best_sample = indices;
+        let len = data.clone().count();
+        *rejected_samples = 0;
+        if len < E::MIN_SAMPLES {
+            return (ConsensusReport::too_few_data(), best_sample);
+        }
+        sampler.reset();
+        termination.reset();
+        verifier.reset();
+        let mut best: Option<(E::Model, f64)> = None;
+        let mut best_inliers = 0;
+        let mut hypotheses = 0;
+        let mut rejected_hypotheses = 0;
+        let mut iterations = 0;
+        while !termination.should_stop(iterations) {
+            iterations += 1;
+            if !sampler.sample(rng, len, &mut indices) {
+                continue;
+            }
+            let sample = Select::new(data.clone(), &indices);
+            if !estimator.is_sample_valid(sample.clone()) || !sample_check.check(sample.clone()) {
+                *rejected_samples += 1;
+                continue;
+            }
+            for model in estimator.estimate(sample.clone()) {
+                hypotheses += 1;
+                if !estimator.is_model_valid(&model, sample.clone())
+                    || !model_check.check(&model, sample.clone())
+                    || !verifier.verify(&model, data.clone())
+                {
+                    rejected_hypotheses += 1;
+                    continue;
+                }
+                let (mut score, mut inliers) = evaluate(&model, data.clone());
+                if best.as_ref().is_none_or(|&(_, best)| score > best) {
+                    let mut model = model;
+                    let optimized =
+                        optimize(rng, termination, &model, sample.clone(), data.clone()).filter(
+                            |optimized| estimator.is_model_valid(optimized, sample.clone()),
+                        );
+                    if let Some(optimized) = optimized {
+                        let (optimized_score, optimized_inliers) =
+                            evaluate(&optimized, data.clone());
+                        if optimized_score > score {
+                            model = optimized;
+                            score = optimized_score;
+                            inliers = optimized_inliers;
+                        }
+                    }
+                    verifier.update(inliers as f64 / len as f64);
+                    termination.update(&BestHypothesis {
+                        score,
+                        inliers,
+                        len,
+                        sample_size: E::MIN_SAMPLES,
+                        decision_threshold: verifier.decision_threshold(),
+                    });
+                    best = Some((model, score));
+                    best_inliers = inliers;
+                    best_sample = indices;
+                }
+            }
+        }
+        let (model, score) = best.unzip();
+        let report = ConsensusReport {
+            model,
+            inliers: Vec::new(),
+            score,
+            iterations,
+            hypotheses,
+            rejected_samples: *rejected_samples,
+            rejected_hypotheses,
+            termination: termination.reason(),
+            confidence: achieved_confidence(
+                best_inliers as f64 / len as f64,
+                E::MIN_SAMPLES,
+                iterations,
+            ),
+        };
+        (report, best_sample)
+    }
+}
+
+/// Iterates over the indices of the data whose residual to `model` is below `threshold`.
+pub(crate) fn inliers<'a, M, Data, I>(
+    model: &'a M,
+    data: I,
+    threshold: f64,
+) -> impl Iterator<Item = usize> + 'a
+where
+    M: Model<Data>,
+    I: Iterator<Item = Data> + 'a,
+{
+    data.enumerate()
+        .filter(move |(_, data)| model.residual(data) < threshold)
+        .map(|(ix, _)| ix)
+}
+
+/// Counts the data whose residual to `model` is below `threshold`.
+pub(crate) fn count_inliers<M, Data, I>(model: &M, data: I, threshold: f64) -> usize
+where
+    M: Model<Data>,
+    I: Iterator<Item = Data>,
+{
+    data.filter(|data| model.residual(data) < threshold).count()
+}
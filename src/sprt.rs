@@ -0,0 +1,234 @@
+use crate::{Model, Verifier};
+
+/// The outcome of verifying a model with [`Sprt::verify`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Verification {
+    /// The model was evaluated on all of the data and it has this many inliers.
+    Accepted { inliers: usize },
+    /// The model was rejected as bad after evaluating `tested` datapoints.
+    Rejected { tested: usize },
+}
+
+/// The sequential probability ratio test (SPRT) from the paper "Randomized RANSAC with Sequential Probability
+/// Ratio Test" by Matas and Chum, which is used by WaldSAC.
+///
+/// Verifying a hypothesis against all of the data is the most expensive part of a consensus algorithm when there
+/// is lots of data. The SPRT evaluates the residuals one at a time and decides after every datapoint if the
+/// hypothesis is likely bad, so that bad hypotheses are typically rejected after only a few residual evaluations.
+///
+/// The test assumes that a datapoint is consistent with a good model with probability `epsilon` (the inlier ratio)
+/// and with a bad model with probability `delta`. Both are estimated as the consensus runs: `epsilon` is updated
+/// through [`Sprt::update_epsilon`] whenever a new best hypothesis is found and `delta` is estimated from the
+/// hypotheses that get rejected.
+///
+/// The consensus implementations in this crate accept an `Sprt` through their `sprt` setting, but it can be used
+/// by any consensus algorithm by calling [`Sprt::reset`], [`Sprt::verify`], and [`Sprt::update_epsilon`].
+#[derive(Clone, Debug)]
+pub struct Sprt {
+    threshold: f64,
+    initial_epsilon: f64,
+    initial_delta: f64,
+    model_time: f64,
+    models_per_sample: f64,
+    epsilon: f64,
+    delta: f64,
+    decision_threshold: f64,
+    rejected: usize,
+    rejected_consistency: f64,
+}
+
+impl Sprt {
+    /// Creates a new `Sprt` which considers a datapoint consistent with a model when its residual is
+    /// below `threshold`.
+    pub fn new(threshold: f64) -> Self {
+        let mut sprt = Self {
+            threshold,
+            initial_epsilon: 0.1,
+            initial_delta: 0.01,
+            model_time: 200.0,
+            models_per_sample: 1.0,
+            epsilon: 0.0,
+            delta: 0.0,
+            decision_threshold: 0.0,
+            rejected: 0,
+            rejected_consistency: 0.0,
+        };
+        sprt.reset();
+        sprt
+    }
+
+    /// The residual below which a datapoint is considered consistent with a model.
+    ///
+    /// Default: specified in [`Sprt::new`]
+    pub fn threshold(self, threshold: f64) -> Self {
+        Self { threshold, ..self }
+    }
+
+    /// The initial estimate of the inlier ratio. This should be a conservative lower bound, since good models
+    /// with a lower inlier ratio are likely to be rejected until a better estimate is found.
+    ///
+    /// Default: `0.1`
+    pub fn initial_epsilon(self, initial_epsilon: f64) -> Self {
+        let mut sprt = Self {
+            initial_epsilon,
+            ..self
+        };
+        sprt.reset();
+        sprt
+    }
+
+    /// The initial estimate of the probability that a datapoint is consistent with a bad model.
+    ///
+    /// Default: `0.01`
+    pub fn initial_delta(self, initial_delta: f64) -> Self {
+        let mut sprt = Self {
+            initial_delta,
+            ..self
+        };
+        sprt.reset();
+        sprt
+    }
+
+    /// The time it takes to estimate the models from a sample, relative to the time it takes to
+    /// compute one residual.
+    ///
+    /// Default: `200.0`
+    pub fn model_time(self, model_time: f64) -> Self {
+        let mut sprt = Self { model_time, ..self };
+        sprt.reset();
+        sprt
+    }
+
+    /// The average number of models estimated from a sample.
+    ///
+    /// Default: `1.0`
+    pub fn models_per_sample(self, models_per_sample: f64) -> Self {
+        let mut sprt = Self {
+            models_per_sample,
+            ..self
+        };
+        sprt.reset();
+        sprt
+    }
+
+    /// The current estimate of the probability that a datapoint is consistent with a good model.
+    pub fn epsilon(&self) -> f64 {
+        self.epsilon
+    }
+
+    /// The current estimate of the probability that a datapoint is consistent with a bad model.
+    pub fn delta(&self) -> f64 {
+        self.delta
+    }
+
+    /// The current decision threshold `A`. A model is rejected once the likelihood ratio of it being bad
+    /// rather than good exceeds `A`, so a good model is rejected with probability about `1 / A`.
+    pub fn decision_threshold(&self) -> f64 {
+        self.decision_threshold
+    }
+
+    /// Resets `epsilon` and `delta` to their initial estimates. Call this before running a consensus on new data.
+    pub fn reset(&mut self) {
+        self.epsilon = self.initial_epsilon;
+        self.delta = self.initial_delta;
+        self.rejected = 0;
+        self.rejected_consistency = 0.0;
+        self.design();
+    }
+
+    /// Updates the estimate of `epsilon` with the inlier ratio of a new best hypothesis.
+    pub fn update_epsilon(&mut self, inlier_ratio: f64) {
+        if inlier_ratio > self.epsilon {
+            self.epsilon = inlier_ratio;
+            self.design();
+        }
+    }
+
+    /// Verifies `model` against `data`, rejecting it as soon as it is likely to be bad.
+    pub fn verify<M, Data, I>(&mut self, model: &M, data: I) -> Verification
+    where
+        M: Model<Data>,
+        I: Iterator<Item = Data>,
+    {
+        // The test can't tell good models from bad ones unless good models have more inliers.
+        let enabled = self.delta < self.epsilon;
+        let consistent_ratio = self.delta / self.epsilon;
+        let inconsistent_ratio = (1.0 - self.delta) / (1.0 - self.epsilon);
+        let mut likelihood_ratio = 1.0;
+        let mut tested = 0;
+        let mut consistent = 0;
+        for data in data {
+            tested += 1;
+            if model.residual(&data) < self.threshold {
+                consistent += 1;
+                likelihood_ratio *= consistent_ratio;
+            } else {
+                likelihood_ratio *= inconsistent_ratio;
+            }
+            if enabled && likelihood_ratio > self.decision_threshold {
+                self.update_delta(consistent as f64 / tested as f64);
+                return Verification::Rejected { tested };
+            }
+        }
+        Verification::Accepted {
+            inliers: consistent,
+        }
+    }
+
+    /// Updates the estimate of `delta` with the consistency of a rejected model.
+    fn update_delta(&mut self, consistency: f64) {
+        self.rejected += 1;
+        self.rejected_consistency +=
+            (consistency - self.rejected_consistency) / self.rejected as f64;
+        // Only redesign the test once the estimate deviates significantly from the one in use.
+        let estimate = self.rejected_consistency.max(f64::EPSILON);
+        if libm::fabs(estimate - self.delta) > 0.1 * self.delta {
+            self.delta = estimate;
+            self.design();
+        }
+    }
+
+    /// Computes the decision threshold `A` that minimizes the expected run time for the current
+    /// `epsilon` and `delta`.
+    fn design(&mut self) {
+        let (epsilon, delta) = (self.epsilon, self.delta);
+        let c = (1.0 - delta) * libm::log((1.0 - delta) / (1.0 - epsilon))
+            + delta * libm::log(delta / epsilon);
+        let k = self.model_time * c / self.models_per_sample + 1.0;
+        let mut a = k;
+        for _ in 0..16 {
+            let next = k + libm::log(a);
+            if libm::fabs(next - a) < 1e-8 {
+                a = next;
+                break;
+            }
+            a = next;
+        }
+        self.decision_threshold = a;
+    }
+}
+
+impl<Data> Verifier<Data> for Sprt {
+    fn reset(&mut self) {
+        Sprt::reset(self);
+    }
+
+    fn verify<M, I>(&mut self, model: &M, data: I) -> bool
+    where
+        M: Model<Data>,
+        I: Iterator<Item = Data> + Clone,
+    {
+        matches!(
+            Sprt::verify(self, model, data),
+            Verification::Accepted { .. }
+        )
+    }
+
+    fn update(&mut self, inlier_ratio: f64) {
+        self.update_epsilon(inlier_ratio);
+    }
+
+    fn decision_threshold(&self) -> Option<f64> {
+        Some(Sprt::decision_threshold(self))
+    }
+}
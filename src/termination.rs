@@ -0,0 +1,819 @@
+use crate::TerminationReason;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+/// A `Termination` criterion decides when a consensus has drawn enough samples.
+///
+/// Criteria can be combined with [`Termination::or`] and [`Termination::and`], such as to stop once an all-inlier
+/// sample has been drawn with some confidence or a time budget runs out, whichever comes first.
+pub trait Termination {
+    /// Called at the start of every search.
+    fn reset(&mut self) {}
+
+    /// Called whenever a new best hypothesis is found.
+    fn update(&mut self, best: &BestHypothesis);
+
+    /// Whether to stop after `iterations` samples have been drawn.
+    fn should_stop(&mut self, iterations: usize) -> bool;
+
+    /// Whether to abandon the current iteration right away, such as when the search was cancelled.
+    ///
+    /// This is checked between the steps of the stages which can take much longer than drawing a sample, such as
+    /// the local optimization and final refinement with a [`Refiner`](crate::Refiner) and the evaluation of the
+    /// hypotheses of [`PreemptiveRansac`](crate::PreemptiveRansac) and [`Arrsac`](crate::Arrsac). Once it returns
+    /// `true`, [`Termination::should_stop`] must return `true` as well.
+    ///
+    /// By default iterations are never interrupted.
+    fn is_interrupted(&mut self) -> bool {
+        false
+    }
+
+    /// Why the search stopped, once [`Termination::should_stop`] has returned `true`.
+    ///
+    /// By default the criterion is considered met, which is reported as [`TerminationReason::Converged`].
+    fn reason(&self) -> TerminationReason {
+        TerminationReason::Converged
+    }
+
+    /// Stops as soon as either this criterion or `other` does.
+    fn or<T>(self, other: T) -> Any<Self, T>
+    where
+        Self: Sized,
+        T: Termination,
+    {
+        Any::new(self, other)
+    }
+
+    /// Stops once both this criterion and `other` do.
+    fn and<T>(self, other: T) -> All<Self, T>
+    where
+        Self: Sized,
+        T: Termination,
+    {
+        All::new(self, other)
+    }
+}
+
+/// A new best hypothesis, as passed to [`Termination::update`].
+#[derive(Copy, Clone, Debug)]
+pub struct BestHypothesis {
+    /// The score of the hypothesis, where higher is better.
+    pub score: f64,
+    /// The number of inliers of the hypothesis.
+    pub inliers: usize,
+    /// The number of datapoints.
+    pub len: usize,
+    /// The number of datapoints in a minimal sample.
+    pub sample_size: usize,
+    /// The decision threshold of the [`Sprt`](crate::Sprt), if the hypotheses are verified with it.
+    pub decision_threshold: Option<f64>,
+}
+
+impl BestHypothesis {
+    /// The fraction of the data that are inliers of the hypothesis.
+    pub fn inlier_ratio(&self) -> f64 {
+        self.inliers as f64 / self.len as f64
+    }
+
+    /// The number of samples needed to draw at least one all-inlier sample with probability `confidence`,
+    /// accounting for the [`Sprt`](crate::Sprt) if it is used, clamped to `max_iterations`.
+    pub fn required_iterations(&self, confidence: f64, max_iterations: usize) -> usize {
+        match self.decision_threshold {
+            Some(decision_threshold) => sprt_required_iterations(
+                confidence,
+                self.inlier_ratio(),
+                self.sample_size,
+                decision_threshold,
+                max_iterations,
+            ),
+            None => required_iterations(
+                confidence,
+                self.inlier_ratio(),
+                self.sample_size,
+                max_iterations,
+            ),
+        }
+    }
+}
+
+/// Stops once an all-inlier sample has been drawn with a given confidence, based on the inlier ratio of the best
+/// hypothesis, or after a maximum number of iterations. This is the termination criterion of RANSAC.
+#[derive(Copy, Clone, Debug)]
+pub struct Adaptive {
+    confidence: f64,
+    max_iterations: usize,
+    iterations: usize,
+}
+
+impl Adaptive {
+    /// Creates a new `Adaptive` which stops once an all-inlier sample has been drawn with probability `confidence`,
+    /// or after `max_iterations`.
+    pub fn new(confidence: f64, max_iterations: usize) -> Self {
+        Self {
+            confidence,
+            max_iterations,
+            iterations: max_iterations,
+        }
+    }
+
+    /// The probability of having drawn at least one all-inlier sample at which iteration stops.
+    ///
+    /// Default: specified in [`Adaptive::new`]
+    pub fn confidence(self, confidence: f64) -> Self {
+        Self { confidence, ..self }
+    }
+
+    /// The maximum number of samples to draw.
+    ///
+    /// Default: specified in [`Adaptive::new`]
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            max_iterations,
+            iterations: max_iterations,
+            ..self
+        }
+    }
+}
+
+impl Termination for Adaptive {
+    fn reset(&mut self) {
+        self.iterations = self.max_iterations;
+    }
+
+    fn update(&mut self, best: &BestHypothesis) {
+        self.iterations = best.required_iterations(self.confidence, self.max_iterations);
+    }
+
+    fn should_stop(&mut self, iterations: usize) -> bool {
+        iterations >= self.iterations
+    }
+
+    fn reason(&self) -> TerminationReason {
+        if self.iterations < self.max_iterations {
+            TerminationReason::Converged
+        } else {
+            TerminationReason::MaxIterations
+        }
+    }
+}
+
+/// Stops after a fixed number of iterations.
+#[derive(Copy, Clone, Debug)]
+pub struct MaxIterations {
+    max_iterations: usize,
+}
+
+impl MaxIterations {
+    /// Creates a new `MaxIterations` which stops after `max_iterations`.
+    pub fn new(max_iterations: usize) -> Self {
+        Self { max_iterations }
+    }
+}
+
+impl Termination for MaxIterations {
+    fn update(&mut self, _best: &BestHypothesis) {}
+
+    fn should_stop(&mut self, iterations: usize) -> bool {
+        iterations >= self.max_iterations
+    }
+
+    fn reason(&self) -> TerminationReason {
+        TerminationReason::MaxIterations
+    }
+}
+
+/// Stops once an all-inlier sample has been drawn with a given confidence, based on the inlier ratio of the best
+/// hypothesis.
+///
+/// Unlike [`Adaptive`], this never stops before a hypothesis is found, so combine it with another criterion
+/// such as [`MaxIterations`].
+#[derive(Copy, Clone, Debug)]
+pub struct Confidence {
+    confidence: f64,
+    iterations: usize,
+}
+
+impl Confidence {
+    /// Creates a new `Confidence` which stops once an all-inlier sample has been drawn with probability
+    /// `confidence`.
+    pub fn new(confidence: f64) -> Self {
+        Self {
+            confidence,
+            iterations: usize::MAX,
+        }
+    }
+}
+
+impl Termination for Confidence {
+    fn reset(&mut self) {
+        self.iterations = usize::MAX;
+    }
+
+    fn update(&mut self, best: &BestHypothesis) {
+        self.iterations = best.required_iterations(self.confidence, usize::MAX);
+    }
+
+    fn should_stop(&mut self, iterations: usize) -> bool {
+        iterations >= self.iterations
+    }
+}
+
+/// A `Clock` tells the time for a [`TimeBudget`], which keeps it independent of the standard library.
+///
+/// It is implemented for closures returning the time, such as `move || start.elapsed()` for a
+/// `std::time::Instant` called `start`.
+pub trait Clock {
+    /// The time elapsed since an arbitrary fixed point, which must never decrease.
+    fn now(&mut self) -> Duration;
+}
+
+impl<F> Clock for F
+where
+    F: FnMut() -> Duration,
+{
+    fn now(&mut self) -> Duration {
+        self()
+    }
+}
+
+/// Stops once a time budget has run out since the start of the search, as told by a [`Clock`].
+#[derive(Copy, Clone, Debug)]
+pub struct TimeBudget<C> {
+    clock: C,
+    budget: Duration,
+    start: Duration,
+}
+
+impl<C> TimeBudget<C>
+where
+    C: Clock,
+{
+    /// Creates a new `TimeBudget` which stops once `budget` has passed on `clock`.
+    pub fn new(clock: C, budget: Duration) -> Self {
+        Self {
+            clock,
+            budget,
+            start: Duration::ZERO,
+        }
+    }
+}
+
+impl<C> Termination for TimeBudget<C>
+where
+    C: Clock,
+{
+    fn reset(&mut self) {
+        self.start = self.clock.now();
+    }
+
+    fn update(&mut self, _best: &BestHypothesis) {}
+
+    fn should_stop(&mut self, _iterations: usize) -> bool {
+        self.clock.now().saturating_sub(self.start) >= self.budget
+    }
+
+    fn reason(&self) -> TerminationReason {
+        TerminationReason::TimeBudget
+    }
+}
+
+/// A `Cancel` tells a [`Cancellation`] whether the search has been cancelled, such as from another thread.
+///
+/// It is implemented for [`AtomicBool`], and for closures returning whether to cancel.
+pub trait Cancel {
+    /// Whether the search has been cancelled.
+    fn is_cancelled(&self) -> bool;
+}
+
+impl Cancel for AtomicBool {
+    fn is_cancelled(&self) -> bool {
+        self.load(Ordering::Relaxed)
+    }
+}
+
+impl Cancel for &AtomicBool {
+    fn is_cancelled(&self) -> bool {
+        self.load(Ordering::Relaxed)
+    }
+}
+
+impl<F> Cancel for F
+where
+    F: Fn() -> bool,
+{
+    fn is_cancelled(&self) -> bool {
+        self()
+    }
+}
+
+/// Stops as soon as the search is cancelled, as told by a [`Cancel`], such as when a user no longer needs the
+/// result.
+///
+/// The cancellation is checked before every sample and, through [`Termination::is_interrupted`], between the steps
+/// of refinement and preemptive evaluation. The best model found so far is still returned. Combine it with the
+/// usual criterion with [`Termination::or`]. [`Prosac`](crate::Prosac), [`Arrsac`](crate::Arrsac) and
+/// [`PreemptiveRansac`](crate::PreemptiveRansac) take it on its own, since they keep their own stopping rules.
+#[derive(Copy, Clone, Debug)]
+pub struct Cancellation<C> {
+    cancel: C,
+}
+
+impl<C> Cancellation<C>
+where
+    C: Cancel,
+{
+    /// Creates a new `Cancellation` which stops once `cancel` says the search is cancelled.
+    pub fn new(cancel: C) -> Self {
+        Self { cancel }
+    }
+}
+
+impl<C> Termination for Cancellation<C>
+where
+    C: Cancel,
+{
+    fn update(&mut self, _best: &BestHypothesis) {}
+
+    fn should_stop(&mut self, _iterations: usize) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    fn is_interrupted(&mut self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    fn reason(&self) -> TerminationReason {
+        TerminationReason::Cancelled
+    }
+}
+
+/// Stops once the score of the best hypothesis hasn't improved for a number of iterations.
+///
+/// This never stops before a hypothesis is found, so combine it with another criterion such as [`MaxIterations`].
+#[derive(Copy, Clone, Debug)]
+pub struct Plateau {
+    patience: usize,
+    min_improvement: f64,
+    best: Option<f64>,
+    iterations: usize,
+    improved: usize,
+}
+
+impl Plateau {
+    /// Creates a new `Plateau` which stops once `patience` samples have been drawn since the score last improved.
+    pub fn new(patience: usize) -> Self {
+        Self {
+            patience,
+            min_improvement: 0.0,
+            best: None,
+            iterations: 0,
+            improved: 0,
+        }
+    }
+
+    /// The amount by which the score must increase to count as an improvement.
+    ///
+    /// Default: `0.0`
+    pub fn min_improvement(self, min_improvement: f64) -> Self {
+        Self {
+            min_improvement,
+            ..self
+        }
+    }
+}
+
+impl Termination for Plateau {
+    fn reset(&mut self) {
+        self.best = None;
+        self.iterations = 0;
+        self.improved = 0;
+    }
+
+    fn update(&mut self, best: &BestHypothesis) {
+        if self
+            .best
+            .is_none_or(|score| best.score > score + self.min_improvement)
+        {
+            self.best = Some(best.score);
+            // The hypothesis was found by the sample after the last one checked.
+            self.improved = self.iterations + 1;
+        }
+    }
+
+    fn should_stop(&mut self, iterations: usize) -> bool {
+        self.iterations = iterations;
+        self.best.is_some() && iterations.saturating_sub(self.improved) >= self.patience
+    }
+}
+
+/// Stops as soon as either of two criteria does, as created by [`Termination::or`].
+///
+/// The reason for stopping is that of the first criterion if it stopped, and otherwise that of the second.
+#[derive(Copy, Clone, Debug)]
+pub struct Any<A, B> {
+    first: A,
+    second: B,
+    first_stopped: bool,
+}
+
+impl<A, B> Any<A, B>
+where
+    A: Termination,
+    B: Termination,
+{
+    /// Creates a new `Any` which stops as soon as either `first` or `second` does.
+    pub fn new(first: A, second: B) -> Self {
+        Self {
+            first,
+            second,
+            first_stopped: false,
+        }
+    }
+}
+
+impl<A, B> Termination for Any<A, B>
+where
+    A: Termination,
+    B: Termination,
+{
+    fn reset(&mut self) {
+        self.first.reset();
+        self.second.reset();
+        self.first_stopped = false;
+    }
+
+    fn update(&mut self, best: &BestHypothesis) {
+        self.first.update(best);
+        self.second.update(best);
+    }
+
+    fn should_stop(&mut self, iterations: usize) -> bool {
+        // Both are always checked so that criteria which track the iterations see all of them.
+        self.first_stopped = self.first.should_stop(iterations);
+        let second_stopped = self.second.should_stop(iterations);
+        self.first_stopped || second_stopped
+    }
+
+    fn is_interrupted(&mut self) -> bool {
+        let first_interrupted = self.first.is_interrupted();
+        let second_interrupted = self.second.is_interrupted();
+        if first_interrupted || second_interrupted {
+            self.first_stopped = first_interrupted;
+        }
+        first_interrupted || second_interrupted
+    }
+
+    fn reason(&self) -> TerminationReason {
+        if self.first_stopped {
+            self.first.reason()
+        } else {
+            self.second.reason()
+        }
+    }
+}
+
+/// Stops once both of two criteria do, as created by [`Termination::and`].
+///
+/// The reason for stopping is [`TerminationReason::Converged`] if either criterion reports it, since both were met,
+/// and otherwise that of the first criterion.
+#[derive(Copy, Clone, Debug)]
+pub struct All<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> All<A, B>
+where
+    A: Termination,
+    B: Termination,
+{
+    /// Creates a new `All` which stops once both `first` and `second` do.
+    pub fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+}
+
+impl<A, B> Termination for All<A, B>
+where
+    A: Termination,
+    B: Termination,
+{
+    fn reset(&mut self) {
+        self.first.reset();
+        self.second.reset();
+    }
+
+    fn update(&mut self, best: &BestHypothesis) {
+        self.first.update(best);
+        self.second.update(best);
+    }
+
+    fn should_stop(&mut self, iterations: usize) -> bool {
+        // Both are always checked so that criteria which track the iterations see all of them.
+        let first_stopped = self.first.should_stop(iterations);
+        let second_stopped = self.second.should_stop(iterations);
+        first_stopped && second_stopped
+    }
+
+    fn is_interrupted(&mut self) -> bool {
+        let first_interrupted = self.first.is_interrupted();
+        let second_interrupted = self.second.is_interrupted();
+        first_interrupted && second_interrupted
+    }
+
+    fn reason(&self) -> TerminationReason {
+        match (self.first.reason(), self.second.reason()) {
+            (TerminationReason::Converged, _) | (_, TerminationReason::Converged) => {
+                TerminationReason::Converged
+            }
+            (reason, _) => reason,
+        }
+    }
+}
+
+/// Computes the number of samples of `sample_size` datapoints needed to draw at least one all-inlier sample with
+/// probability `confidence`, given the fraction of the data that are inliers, which is `log(1 - p) / log(1 - w^m)`.
+///
+/// The result is between `1` and `max_iterations`. With an inlier ratio of `1`, a single sample is enough, and with
+/// an inlier ratio of `0`, no number of samples is.
+pub fn required_iterations(
+    confidence: f64,
+    inlier_ratio: f64,
+    sample_size: usize,
+    max_iterations: usize,
+) -> usize {
+    iterations_for(
+        confidence,
+        libm::pow(inlier_ratio, sample_size as f64),
+        max_iterations,
+    )
+}
+
+/// The same as [`required_iterations`], but for a consensus which verifies hypotheses with the [`Sprt`] using the
+/// decision threshold `A`, as in "Optimal Randomized RANSAC" by Chum and Matas.
+///
+/// The SPRT rejects a good model with probability about `1 / A`, so more samples are needed to draw an all-inlier
+/// sample whose model also passes the test.
+///
+/// [`Sprt`]: crate::Sprt
+pub fn sprt_required_iterations(
+    confidence: f64,
+    inlier_ratio: f64,
+    sample_size: usize,
+    decision_threshold: f64,
+    max_iterations: usize,
+) -> usize {
+    let accepted = (1.0 - decision_threshold.recip()).clamp(0.0, 1.0);
+    iterations_for(
+        confidence,
+        libm::pow(inlier_ratio, sample_size as f64) * accepted,
+        max_iterations,
+    )
+}
+
+/// The same as [`required_iterations`], but for samples drawn from a pool of the `pool_size` highest quality
+/// datapoints of which `pool_inliers` are inliers, as in PROSAC.
+///
+/// Since the datapoints of a sample are distinct, the probability of an all-inlier sample is computed without
+/// replacement, which matters for the small pools PROSAC starts from.
+pub fn prosac_required_iterations(
+    confidence: f64,
+    pool_inliers: usize,
+    pool_size: usize,
+    sample_size: usize,
+    max_iterations: usize,
+) -> usize {
+    let all_inliers = if pool_inliers < sample_size || pool_size < sample_size {
+        0.0
+    } else {
+        (0..sample_size)
+            .map(|i| (pool_inliers - i) as f64 / (pool_size - i) as f64)
+            .product()
+    };
+    iterations_for(confidence, all_inliers, max_iterations)
+}
+
+/// Computes the probability of having drawn at least one all-inlier sample of `sample_size` datapoints in
+/// `iterations` samples, given the fraction of the data that are inliers.
+///
+/// This is the inverse of [`required_iterations`].
+pub fn achieved_confidence(inlier_ratio: f64, sample_size: usize, iterations: usize) -> f64 {
+    let all_inliers = libm::pow(inlier_ratio, sample_size as f64);
+    if all_inliers >= 1.0 {
+        return if iterations > 0 { 1.0 } else { 0.0 };
+    }
+    if all_inliers <= 0.0 || all_inliers.is_nan() {
+        return 0.0;
+    }
+    -libm::expm1(iterations as f64 * libm::log1p(-all_inliers))
+}
+
+/// Computes the number of samples needed to draw at least one good sample with probability `confidence`, when
+/// every sample is good with probability `good`.
+fn iterations_for(confidence: f64, good: f64, max_iterations: usize) -> usize {
+    if good >= 1.0 {
+        return 1;
+    }
+    if good <= 0.0 || good.is_nan() {
+        return max_iterations;
+    }
+    let iterations = libm::ceil(libm::log1p(-confidence) / libm::log1p(-good));
+    if iterations.is_nan() || iterations >= max_iterations as f64 {
+        max_iterations
+    } else {
+        (iterations as usize).max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn required_iterations_without_inliers_is_max() {
+        assert_eq!(required_iterations(0.99, 0.0, 4, 1000), 1000);
+        assert_eq!(sprt_required_iterations(0.99, 0.0, 4, 100.0, 1000), 1000);
+        assert_eq!(prosac_required_iterations(0.99, 0, 10, 4, 1000), 1000);
+        assert_eq!(prosac_required_iterations(0.99, 3, 10, 4, 1000), 1000);
+        assert_eq!(achieved_confidence(0.0, 4, 1000), 0.0);
+    }
+
+    #[test]
+    fn required_iterations_with_only_inliers_is_one() {
+        assert_eq!(required_iterations(0.99, 1.0, 4, 1000), 1);
+        assert_eq!(prosac_required_iterations(0.99, 10, 10, 4, 1000), 1);
+        assert_eq!(achieved_confidence(1.0, 4, 1), 1.0);
+        assert_eq!(achieved_confidence(1.0, 4, 0), 0.0);
+    }
+
+    #[test]
+    fn required_iterations_with_nan_is_max() {
+        assert_eq!(required_iterations(0.99, f64::NAN, 4, 1000), 1000);
+        assert_eq!(required_iterations(f64::NAN, 0.5, 4, 1000), 1000);
+        assert_eq!(sprt_required_iterations(0.99, 0.5, 4, f64::NAN, 1000), 1000);
+        assert_eq!(achieved_confidence(f64::NAN, 4, 1000), 0.0);
+    }
+
+    #[test]
+    fn required_iterations_for_certainty_is_max() {
+        assert_eq!(required_iterations(1.0, 0.5, 4, 1000), 1000);
+        assert_eq!(sprt_required_iterations(1.0, 0.5, 4, 100.0, 1000), 1000);
+        assert_eq!(prosac_required_iterations(1.0, 5, 10, 4, 1000), 1000);
+        assert_eq!(required_iterations(1.0, 1.0, 4, 1000), 1);
+    }
+
+    #[test]
+    fn required_iterations_matches_the_closed_form() {
+        // log(1 - 0.99) / log(1 - 0.5^4) = 71.4
+        assert_eq!(required_iterations(0.99, 0.5, 4, 1000), 72);
+        assert_eq!(required_iterations(0.99, 0.5, 4, 50), 50);
+    }
+
+    #[test]
+    fn sprt_required_iterations_accounts_for_rejected_good_models() {
+        let plain = required_iterations(0.99, 0.5, 4, 1000);
+        assert_eq!(
+            sprt_required_iterations(0.99, 0.5, 4, f64::INFINITY, 1000),
+            plain
+        );
+        assert!(sprt_required_iterations(0.99, 0.5, 4, 10.0, 1000) > plain);
+        assert_eq!(sprt_required_iterations(0.99, 0.5, 4, 1.0, 1000), 1000);
+    }
+
+    #[test]
+    fn prosac_required_iterations_samples_without_replacement() {
+        let with_replacement = required_iterations(0.99, 0.5, 4, 1000);
+        assert!(prosac_required_iterations(0.99, 5, 10, 4, 1000) > with_replacement);
+        let large = prosac_required_iterations(0.99, 50_000, 100_000, 4, 1000);
+        assert!(large.abs_diff(with_replacement) <= 1);
+    }
+
+    #[test]
+    fn achieved_confidence_round_trips_required_iterations() {
+        for &confidence in &[0.5, 0.9, 0.99, 0.999] {
+            for &inlier_ratio in &[0.05, 0.2, 0.5, 0.8, 0.95] {
+                for sample_size in 1..8 {
+                    let iterations =
+                        required_iterations(confidence, inlier_ratio, sample_size, usize::MAX);
+                    let achieved = achieved_confidence(inlier_ratio, sample_size, iterations);
+                    assert!(achieved >= confidence - 1e-12);
+                    if iterations > 1 {
+                        let fewer = achieved_confidence(inlier_ratio, sample_size, iterations - 1);
+                        assert!(fewer < confidence);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn only_cancellation_interrupts() {
+        let mut cancelled = Cancellation::new(|| true);
+        assert!(cancelled.is_interrupted());
+        assert!(!Cancellation::new(|| false).is_interrupted());
+        assert!(!MaxIterations::new(0).is_interrupted());
+        assert!(!Adaptive::new(0.99, 0).is_interrupted());
+    }
+
+    #[test]
+    fn combinators_interrupt_like_they_stop() {
+        let mut any = MaxIterations::new(10).or(Cancellation::new(|| true));
+        assert!(any.is_interrupted());
+        assert_eq!(any.reason(), TerminationReason::Cancelled);
+        let mut any = Cancellation::new(|| true).or(MaxIterations::new(10));
+        assert!(any.is_interrupted());
+        assert_eq!(any.reason(), TerminationReason::Cancelled);
+        assert!(!MaxIterations::new(0)
+            .or(Cancellation::new(|| false))
+            .is_interrupted());
+        assert!(!MaxIterations::new(0)
+            .and(Cancellation::new(|| true))
+            .is_interrupted());
+        assert!(Cancellation::new(|| true)
+            .and(Cancellation::new(|| true))
+            .is_interrupted());
+    }
+
+    fn hypothesis(score: f64) -> BestHypothesis {
+        BestHypothesis {
+            score,
+            inliers: 10,
+            len: 100,
+            sample_size: 2,
+            decision_threshold: None,
+        }
+    }
+
+    #[test]
+    fn plateau_stops_once_the_score_stops_improving() {
+        let mut plateau = Plateau::new(3);
+        plateau.reset();
+        assert!(!plateau.should_stop(100));
+        plateau.update(&hypothesis(1.0));
+        // The hypothesis was found by the 101st sample.
+        assert!(!plateau.should_stop(101));
+        assert!(!plateau.should_stop(103));
+        plateau.update(&hypothesis(2.0));
+        assert!(!plateau.should_stop(104));
+        assert!(!plateau.should_stop(106));
+        assert!(plateau.should_stop(107));
+        assert_eq!(plateau.reason(), TerminationReason::Converged);
+    }
+
+    #[test]
+    fn plateau_ignores_small_improvements() {
+        let mut plateau = Plateau::new(2).min_improvement(0.5);
+        plateau.update(&hypothesis(1.0));
+        assert!(!plateau.should_stop(1));
+        plateau.update(&hypothesis(1.25));
+        assert!(!plateau.should_stop(2));
+        assert!(plateau.should_stop(3));
+        plateau.reset();
+        assert!(!plateau.should_stop(3));
+    }
+
+    #[test]
+    fn plateau_does_not_underflow_before_the_improving_sample_is_counted() {
+        let mut plateau = Plateau::new(0);
+        plateau.should_stop(5);
+        plateau.update(&hypothesis(1.0));
+        // The improvement is attributed to the 6th sample, which hasn't been counted yet.
+        assert!(plateau.should_stop(5));
+        let mut plateau = Plateau::new(1);
+        plateau.should_stop(5);
+        plateau.update(&hypothesis(1.0));
+        assert!(!plateau.should_stop(5));
+    }
+
+    #[test]
+    fn any_stops_with_the_first_criterion_that_does() {
+        let mut any = MaxIterations::new(10).or(Plateau::new(2));
+        any.reset();
+        any.update(&hypothesis(1.0));
+        assert!(!any.should_stop(2));
+        assert!(any.should_stop(3));
+        assert_eq!(any.reason(), TerminationReason::Converged);
+        any.reset();
+        assert!(!any.should_stop(9));
+        assert!(any.should_stop(10));
+        assert_eq!(any.reason(), TerminationReason::MaxIterations);
+        let mut any = MaxIterations::new(5).or(MaxIterations::new(10));
+        assert!(any.should_stop(10));
+        assert_eq!(any.reason(), TerminationReason::MaxIterations);
+    }
+
+    #[test]
+    fn all_stops_once_both_criteria_do() {
+        let mut all = MaxIterations::new(10).and(Plateau::new(2));
+        all.reset();
+        assert!(!all.should_stop(20));
+        all.update(&hypothesis(1.0));
+        assert!(!all.should_stop(22));
+        assert!(all.should_stop(23));
+        assert_eq!(all.reason(), TerminationReason::Converged);
+        let mut all = MaxIterations::new(10).and(MaxIterations::new(5));
+        assert!(!all.should_stop(5));
+        assert!(all.should_stop(10));
+        assert_eq!(all.reason(), TerminationReason::MaxIterations);
+    }
+}
@@ -0,0 +1,532 @@
+use crate::sample::Select;
+use crate::search::Search;
+use crate::termination::achieved_confidence;
+use crate::{
+    Adaptive, Consensus, ConsensusReport, CountScorer, Estimator, InnerRansac, Model, Refiner,
+    Refit, ReportingConsensus, Sampler, Score, Scorer, Termination, Uniform,
+};
+use alloc::vec::Vec;
+use rand_core::RngCore;
+
+/// A `SampleCheck` rejects minimal samples before a model is estimated from them, such as samples whose points
+/// are collinear when estimating a homography.
+pub trait SampleCheck<Data> {
+    /// Returns `false` if no model should be estimated from the datapoints of `sample`.
+    fn check<I>(&mut self, sample: I) -> bool
+    where
+        I: Iterator<Item = Data> + Clone;
+}
+
+/// A `ModelCheck` rejects models right after they are estimated, using only the sample they were estimated from,
+/// such as models which violate cheirality.
+pub trait ModelCheck<M, Data> {
+    /// Returns `false` if `model`, estimated from the datapoints of `sample`, should be discarded.
+    fn check<I>(&mut self, model: &M, sample: I) -> bool
+    where
+        I: Iterator<Item = Data> + Clone;
+}
+
+/// A `Verifier` quickly rejects bad models before they are scored on all of the data, such as the [`Sprt`].
+///
+/// [`Sprt`]: crate::Sprt
+pub trait Verifier<Data> {
+    /// Called at the start of every search.
+    fn reset(&mut self) {}
+
+    /// Returns `false` if `model` is not worth scoring.
+    fn verify<M, I>(&mut self, model: &M, data: I) -> bool
+    where
+        M: Model<Data>,
+        I: Iterator<Item = Data> + Clone;
+
+    /// Called whenever a new best hypothesis is found, with its inlier ratio.
+    fn update(&mut self, _inlier_ratio: f64) {}
+
+    /// The decision threshold `A` of a sequential test which also rejects good models with probability about
+    /// `1 / A`, so that the [`Termination`] criterion can draw more samples to make up for them.
+    ///
+    /// By default there is none.
+    fn decision_threshold(&self) -> Option<f64> {
+        None
+    }
+}
+
+/// Accepts every sample and every model. This is the default for the checks and verification of [`Usac`].
+#[derive(Copy, Clone, Debug, Default)]
+pub struct AcceptAll;
+
+impl<Data> SampleCheck<Data> for AcceptAll {
+    fn check<I>(&mut self, _sample: I) -> bool
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        true
+    }
+}
+
+impl<M, Data> ModelCheck<M, Data> for AcceptAll {
+    fn check<I>(&mut self, _model: &M, _sample: I) -> bool
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        true
+    }
+}
+
+impl<Data> Verifier<Data> for AcceptAll {
+    fn verify<M, I>(&mut self, _model: &M, _data: I) -> bool
+    where
+        M: Model<Data>,
+        I: Iterator<Item = Data> + Clone,
+    {
+        true
+    }
+}
+
+/// An optional verifier, which accepts every model when it is `None`.
+impl<Data, V> Verifier<Data> for Option<V>
+where
+    V: Verifier<Data>,
+{
+    fn reset(&mut self) {
+        if let Some(verifier) = self {
+            verifier.reset();
+        }
+    }
+
+    fn verify<M, I>(&mut self, model: &M, data: I) -> bool
+    where
+        M: Model<Data>,
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.as_mut()
+            .is_none_or(|verifier| verifier.verify(model, data))
+    }
+
+    fn update(&mut self, inlier_ratio: f64) {
+        if let Some(verifier) = self {
+            verifier.update(inlier_ratio);
+        }
+    }
+
+    fn decision_threshold(&self) -> Option<f64> {
+        self.as_ref().and_then(Verifier::decision_threshold)
+    }
+}
+
+/// A modular consensus in the style of USAC from the paper "USAC: A Universal Framework for Random Sample
+/// Consensus" by Raguram et al.
+///
+/// Every stage of the hypothesize-and-verify loop is a trait which can be swapped independently:
+///
+/// 1. The [`Sampler`] draws a minimal sample. Default: [`Uniform`]
+/// 2. [`Estimator::is_sample_valid`] and the [`SampleCheck`] reject degenerate samples. Default: [`AcceptAll`]
+/// 3. The [`Estimator`] estimates models from the sample.
+/// 4. [`Estimator::is_model_valid`] and the [`ModelCheck`] reject implausible models. Default: [`AcceptAll`]
+/// 5. The [`Verifier`] rejects bad models early, such as the [`Sprt`](crate::Sprt). Default: [`AcceptAll`]
+/// 6. The [`Scorer`] scores the models on all of the data. Default: [`CountScorer`]
+/// 7. The local optimization [`Refiner`] improves every new best model. Default: [`InnerRansac`]
+/// 8. The [`Termination`] criterion decides when to stop sampling. Default: [`Adaptive`]
+/// 9. The final [`Refiner`] polishes the best model. Default: [`Refit`]
+///
+/// The refiners consider a datapoint an inlier when its residual is below the threshold, which also configures
+/// the default scorer. A refined model is only kept if it passes [`Estimator::is_model_valid`] for the sample the
+/// model was estimated from. The inliers returned by [`Consensus::model_inliers`] are the inliers according to
+/// the scorer.
+#[derive(Clone, Debug)]
+pub struct Usac<
+    R,
+    S = Uniform,
+    K = AcceptAll,
+    M = AcceptAll,
+    V = AcceptAll,
+    C = CountScorer,
+    L = InnerRansac,
+    T = Adaptive,
+    F = Refit,
+> {
+    threshold: f64,
+    rng: R,
+    rejected_samples: usize,
+    sampler: S,
+    sample_check: K,
+    model_check: M,
+    verifier: V,
+    scorer: C,
+    local_optimization: L,
+    termination: T,
+    final_refinement: F,
+}
+
+impl<R> Usac<R>
+where
+    R: RngCore,
+{
+    /// Creates a new `Usac` which considers a datapoint an inlier when its residual is below `threshold`.
+    ///
+    /// `rng` is used to draw the samples. By default, at most `1000` iterations are run and iteration stops
+    /// early once an all-inlier sample has been drawn with a confidence of `0.99`.
+    pub fn new(threshold: f64, rng: R) -> Self {
+        Self {
+            threshold,
+            rng,
+            rejected_samples: 0,
+            sampler: Uniform,
+            sample_check: AcceptAll,
+            model_check: AcceptAll,
+            verifier: AcceptAll,
+            scorer: CountScorer::new(threshold),
+            local_optimization: InnerRansac::new(),
+            termination: Adaptive::new(0.99, 1000),
+            final_refinement: Refit::new(),
+        }
+    }
+}
+
+impl<R, S, K, M, V, C, L, T, F> Usac<R, S, K, M, V, C, L, T, F>
+where
+    R: RngCore,
+{
+    /// The residual below which the refiners consider a datapoint an inlier. This doesn't change the scorer.
+    ///
+    /// Default: specified in [`Usac::new`]
+    pub fn threshold(self, threshold: f64) -> Self {
+        Self { threshold, ..self }
+    }
+
+    /// The [`Sampler`] which draws the minimal samples.
+    ///
+    /// Default: [`Uniform`]
+    pub fn sampler<X>(self, sampler: X) -> Usac<R, X, K, M, V, C, L, T, F> {
+        Usac {
+            threshold: self.threshold,
+            rng: self.rng,
+            rejected_samples: self.rejected_samples,
+            sampler,
+            sample_check: self.sample_check,
+            model_check: self.model_check,
+            verifier: self.verifier,
+            scorer: self.scorer,
+            local_optimization: self.local_optimization,
+            termination: self.termination,
+            final_refinement: self.final_refinement,
+        }
+    }
+
+    /// The [`SampleCheck`] which rejects samples before a model is estimated from them.
+    ///
+    /// Default: [`AcceptAll`]
+    pub fn sample_check<X>(self, sample_check: X) -> Usac<R, S, X, M, V, C, L, T, F> {
+        Usac {
+            threshold: self.threshold,
+            rng: self.rng,
+            rejected_samples: self.rejected_samples,
+            sampler: self.sampler,
+            sample_check,
+            model_check: self.model_check,
+            verifier: self.verifier,
+            scorer: self.scorer,
+            local_optimization: self.local_optimization,
+            termination: self.termination,
+            final_refinement: self.final_refinement,
+        }
+    }
+
+    /// The [`ModelCheck`] which rejects models right after they are estimated.
+    ///
+    /// Default: [`AcceptAll`]
+    pub fn model_check<X>(self, model_check: X) -> Usac<R, S, K, X, V, C, L, T, F> {
+        Usac {
+            threshold: self.threshold,
+            rng: self.rng,
+            rejected_samples: self.rejected_samples,
+            sampler: self.sampler,
+            sample_check: self.sample_check,
+            model_check,
+            verifier: self.verifier,
+            scorer: self.scorer,
+            local_optimization: self.local_optimization,
+            termination: self.termination,
+            final_refinement: self.final_refinement,
+        }
+    }
+
+    /// The [`Verifier`] which rejects bad models before they are scored, such as the [`Sprt`](crate::Sprt).
+    ///
+    /// Default: [`AcceptAll`]
+    pub fn verifier<X>(self, verifier: X) -> Usac<R, S, K, M, X, C, L, T, F> {
+        Usac {
+            threshold: self.threshold,
+            rng: self.rng,
+            rejected_samples: self.rejected_samples,
+            sampler: self.sampler,
+            sample_check: self.sample_check,
+            model_check: self.model_check,
+            verifier,
+            scorer: self.scorer,
+            local_optimization: self.local_optimization,
+            termination: self.termination,
+            final_refinement: self.final_refinement,
+        }
+    }
+
+    /// The [`Scorer`] which scores the models and decides which datapoints are inliers.
+    ///
+    /// Default: [`CountScorer`] with the threshold specified in [`Usac::new`]
+    pub fn scorer<X>(self, scorer: X) -> Usac<R, S, K, M, V, X, L, T, F> {
+        Usac {
+            threshold: self.threshold,
+            rng: self.rng,
+            rejected_samples: self.rejected_samples,
+            sampler: self.sampler,
+            sample_check: self.sample_check,
+            model_check: self.model_check,
+            verifier: self.verifier,
+            scorer,
+            local_optimization: self.local_optimization,
+            termination: self.termination,
+            final_refinement: self.final_refinement,
+        }
+    }
+
+    /// The [`Refiner`] used to locally optimize new best models.
+    ///
+    /// Default: [`InnerRansac::new`]
+    pub fn local_optimization<X>(self, local_optimization: X) -> Usac<R, S, K, M, V, C, X, T, F> {
+        Usac {
+            threshold: self.threshold,
+            rng: self.rng,
+            rejected_samples: self.rejected_samples,
+            sampler: self.sampler,
+            sample_check: self.sample_check,
+            model_check: self.model_check,
+            verifier: self.verifier,
+            scorer: self.scorer,
+            local_optimization,
+            termination: self.termination,
+            final_refinement: self.final_refinement,
+        }
+    }
+
+    /// The [`Termination`] criterion which decides when to stop drawing samples.
+    ///
+    /// Default: [`Adaptive`] with a confidence of `0.99` and at most `1000` iterations
+    pub fn termination<X>(self, termination: X) -> Usac<R, S, K, M, V, C, L, X, F> {
+        Usac {
+            threshold: self.threshold,
+            rng: self.rng,
+            rejected_samples: self.rejected_samples,
+            sampler: self.sampler,
+            sample_check: self.sample_check,
+            model_check: self.model_check,
+            verifier: self.verifier,
+            scorer: self.scorer,
+            local_optimization: self.local_optimization,
+            termination,
+            final_refinement: self.final_refinement,
+        }
+    }
+
+    /// The [`Refiner`] used to polish the best model once sampling stops.
+    ///
+    /// Default: [`Refit::new`]
+    pub fn final_refinement<X>(self, final_refinement: X) -> Usac<R, S, K, M, V, C, L, T, X> {
+        Usac {
+            threshold: self.threshold,
+            rng: self.rng,
+            rejected_samples: self.rejected_samples,
+            sampler: self.sampler,
+            sample_check: self.sample_check,
+            model_check: self.model_check,
+            verifier: self.verifier,
+            scorer: self.scorer,
+            local_optimization: self.local_optimization,
+            termination: self.termination,
+            final_refinement,
+        }
+    }
+
+    /// The number of minimal samples rejected by [`Estimator::is_sample_valid`] or the [`SampleCheck`] during
+    /// the last search.
+    pub fn rejected_samples(&self) -> usize {
+        self.rejected_samples
+    }
+}
+
+impl<R, S, K, M, V, C, L, T, F> Usac<R, S, K, M, V, C, L, T, F>
+where
+    R: RngCore,
+    S: Sampler,
+    C: Scorer,
+    T: Termination,
+{
+    /// Runs the search and reports the best model, without its inliers.
+    fn search<E, Data, I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        E: Estimator<Data>,
+        K: SampleCheck<Data>,
+        M: ModelCheck<E::Model, Data>,
+        V: Verifier<Data>,
+        L: Refiner<E, Data>,
+        F: Refiner<E, Data>,
+        I: Iterator<Item = Data> + Clone,
+    {
+        let threshold = self.threshold;
+        let scorer = &mut self.scorer;
+        let local_optimization = &mut self.local_optimization;
+        let (mut report, best_sample) = Search {
+            rng: &mut self.rng,
+            sampler: &mut self.sampler,
+            sample_check: &mut self.sample_check,
+            model_check: &mut self.model_check,
+            verifier: &mut self.verifier,
+            rejected_samples: &mut self.rejected_samples,
+            termination: &mut self.termination,
+        }
+        .run_with_sample(
+            estimator,
+            data.clone(),
+            |model, data| {
+                let score = evaluate(scorer, model, data);
+                (score.value, score.inliers)
+            },
+            |rng, termination, model, _, data| {
+                local_optimization.refine(estimator, model, data, threshold, rng, termination)
+            },
+        );
+
+        // The final refinement replaces the best model if it scores at least as well. It is skipped when the
+        // search was interrupted, since the caller no longer wants to wait for it.
+        if self.termination.is_interrupted() {
+            return report;
+        }
+        if let (Some(model), Some(score)) = (&report.model, report.score) {
+            let refined = self
+                .final_refinement
+                .refine(
+                    estimator,
+                    model,
+                    data.clone(),
+                    self.threshold,
+                    &mut self.rng,
+                    &mut self.termination,
+                )
+                .filter(|refined| {
+                    estimator.is_model_valid(refined, Select::new(data.clone(), &best_sample))
+                });
+            if let Some(refined) = refined {
+                let refined_score = evaluate(&mut self.scorer, &refined, data.clone());
+                if refined_score.value >= score {
+                    report.confidence = achieved_confidence(
+                        refined_score.inliers as f64 / data.count() as f64,
+                        E::MIN_SAMPLES,
+                        report.iterations,
+                    );
+                    report.model = Some(refined);
+                    report.score = Some(refined_score.value);
+                }
+            }
+        }
+        report
+    }
+}
+
+impl<E, R, S, K, M, V, C, L, T, F, Data> Consensus<E, Data> for Usac<R, S, K, M, V, C, L, T, F>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    S: Sampler,
+    K: SampleCheck<Data>,
+    M: ModelCheck<E::Model, Data>,
+    V: Verifier<Data>,
+    C: Scorer,
+    L: Refiner<E, Data>,
+    T: Termination,
+    F: Refiner<E, Data>,
+{
+    type Inliers = Vec<usize>;
+
+    fn model<I>(&mut self, estimator: &E, data: I) -> Option<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        self.search(estimator, data).model
+    }
+
+    fn model_inliers<I>(&mut self, estimator: &E, data: I) -> Option<(E::Model, Self::Inliers)>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let report = self.model_report(estimator, data);
+        Some((report.model?, report.inliers))
+    }
+}
+
+impl<E, R, S, K, M, V, C, L, T, F, Data> ReportingConsensus<E, Data>
+    for Usac<R, S, K, M, V, C, L, T, F>
+where
+    E: Estimator<Data>,
+    R: RngCore,
+    S: Sampler,
+    K: SampleCheck<Data>,
+    M: ModelCheck<E::Model, Data>,
+    V: Verifier<Data>,
+    C: Scorer,
+    L: Refiner<E, Data>,
+    T: Termination,
+    F: Refiner<E, Data>,
+{
+    fn model_report<I>(&mut self, estimator: &E, data: I) -> ConsensusReport<E::Model>
+    where
+        I: Iterator<Item = Data> + Clone,
+    {
+        let mut report = self.search(estimator, data.clone());
+        if let Some(model) = &report.model {
+            report.inliers = self.scorer.inliers(data.map(|data| model.residual(&data)));
+        }
+        report
+    }
+}
+
+/// Scores `model` on all of the data.
+fn evaluate<C, M, Data, I>(scorer: &mut C, model: &M, data: I) -> Score
+where
+    C: Scorer,
+    M: Model<Data>,
+    I: Iterator<Item = Data>,
+{
+    scorer.score(data.map(|data| model.residual(&data)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{noisy_line, rng, LineEstimator};
+    use crate::{Cancellation, TerminationReason};
+
+    #[test]
+    fn cancellation_skips_the_final_refinement() {
+        let data = noisy_line(0, 100, 100);
+        let estimator = LineEstimator::default();
+        // Cancel as soon as the local optimization of the first hypothesis has fit a model.
+        let mut consensus = Usac::new(0.2, rng(0)).termination(
+            Adaptive::new(0.99, 1000).or(Cancellation::new(|| estimator.fits.get() > 0)),
+        );
+        let report = consensus.model_report(&estimator, data.iter().copied());
+        assert_eq!(estimator.fits.get(), 1);
+        assert_eq!(report.iterations, 1);
+        assert_eq!(report.termination, TerminationReason::Cancelled);
+        assert!(report.model.is_some());
+    }
+
+    #[test]
+    fn final_refinement_runs_without_cancellation() {
+        let data = noisy_line(0, 100, 100);
+        let estimator = LineEstimator::default();
+        let mut consensus = Usac::new(0.2, rng(0)).local_optimization(Refit::new().iterations(0));
+        let report = consensus.model_report(&estimator, data.iter().copied());
+        assert!(estimator.fits.get() > 0);
+        assert_eq!(report.termination, TerminationReason::Converged);
+        assert!(report.inliers.len() >= 95);
+    }
+}